@@ -0,0 +1,107 @@
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::PathBuf;
+
+use clap::Args;
+use dechst::backend::BackendWrite;
+use dechst::obj::lock::{Exclusive, Shared};
+use dechst::process::build::snapshot::SnapshotBuilder;
+use dechst::repo::marker::LockMarker;
+use dechst::repo::{DecryptedRepo, LockedRepo};
+use dechst::source::fs::FsSource;
+use dechst::source::stdin::StdinSource;
+use dechst::source::Source;
+
+use crate::opts::{GlobalOpts, RepoOpts};
+use crate::util::lock_repo;
+
+#[derive(Debug, Args)]
+pub struct Opts {
+	#[arg(
+		required_unless_present = "stdin",
+		conflicts_with = "stdin",
+		value_hint = clap::ValueHint::DirPath
+	)]
+	path: Option<PathBuf>,
+
+	#[arg(long)]
+	stdin: bool,
+
+	#[arg(short, long = "tag")]
+	tags: Vec<String>,
+
+	#[arg(long)]
+	name: Option<String>,
+
+	#[arg(long)]
+	description: Option<String>,
+}
+
+pub fn execute<B: BackendWrite>(
+	_: GlobalOpts,
+	repo_opts: RepoOpts,
+	cmd: Opts,
+	repo: DecryptedRepo<B>,
+) -> anyhow::Result<()> {
+	let Opts {
+		path,
+		stdin,
+		tags,
+		name,
+		description,
+	} = cmd;
+
+	let marker = LockMarker::READ
+		.index::<Exclusive>()
+		.snapshot::<Exclusive>()
+		.pack::<Exclusive>();
+
+	let mut repo = lock_repo(repo, marker, &repo_opts)?;
+
+	let (root, path) = if stdin {
+		(OsString::from("stdin"), None)
+	} else {
+		let path = path.expect("Path to be required without `--stdin`");
+		let path = std::fs::canonicalize(&path)?;
+
+		anyhow::ensure!(path.is_dir(), "`{}` is not a directory", path.display());
+
+		(path.clone().into_os_string(), Some(path))
+	};
+
+	let mut builder = SnapshotBuilder::new(root).tags(tags);
+
+	if let Some(name) = name {
+		builder = builder.name(name);
+	}
+
+	if let Some(description) = description {
+		builder = builder.description(description);
+	}
+
+	if let Some(path) = path {
+		println!("Creating backup of {}", path.display());
+		build(builder, &mut repo, &FsSource::new(path))
+	} else {
+		println!("Creating backup from stdin");
+		build(builder, &mut repo, &StdinSource)
+	}
+}
+
+fn build<S, B>(
+	builder: SnapshotBuilder,
+	repo: &mut LockedRepo<B, Shared, Exclusive, Shared, Exclusive, Exclusive>,
+	source: &S,
+) -> anyhow::Result<()>
+where
+	S: Source,
+	S::Error: Send + Sync + 'static,
+	S::Read: Read + 'static,
+	B: BackendWrite,
+{
+	let snapshot = builder.build(repo, source)?;
+
+	println!("Created snapshot {}", snapshot.id());
+
+	Ok(())
+}
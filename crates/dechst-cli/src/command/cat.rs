@@ -10,6 +10,7 @@ use dechst::obj::config::Config;
 use dechst::obj::index::Index;
 use dechst::obj::key::{EncryptedKey, Key};
 use dechst::obj::lock::Lock;
+use dechst::obj::snapshot::Snapshot;
 use dechst::obj::{self, RepoObject};
 use dechst::process::format::{Format, FormatterParams};
 use dechst::process::{pipeline, Instanciate};
@@ -38,7 +39,7 @@ struct IdOpt {
 
 impl IdOpt {
 	pub fn is_full(&self) -> bool {
-		self.id.len() == id::WIDTH / 2
+		self.id.len() == id::WIDTH * 2
 	}
 
 	pub fn to_id(&self) -> Option<Id> {
@@ -90,6 +91,7 @@ pub fn execute<B: BackendWrite>(
 		ObjectKind::Key(id) => cat_key(backend, format, key, key_id, id.into_id()),
 		ObjectKind::Lock(id) => cat_obj::<_, Lock>(backend, format, &key, &id),
 		ObjectKind::Index(id) => cat_obj::<_, Index>(backend, format, &key, &id),
+		ObjectKind::Snapshot(id) => cat_obj::<_, Snapshot>(backend, format, &key, &id),
 		_ => unimplemented!(),
 	}
 }
@@ -141,7 +143,7 @@ where
 
 		let password = Password::ask(format!("Enter passphrase for key {id}: ").as_str())?;
 
-		key.decrypt(password.as_bytes())
+		key.decrypt(password.as_bytes())?
 	} else {
 		key
 	};
@@ -159,8 +161,8 @@ where
 	let id = resolve_id(&backend, V::KIND, id)?;
 	let obj: V = get_obj(&backend, &key, &id)?;
 
-	println!("{}: {id}", V::KIND);
-	println!("{obj:#?}");
+	log::debug!("{}: {id}", V::KIND);
+	format.print(&obj);
 
 	Ok(())
 }
@@ -0,0 +1,89 @@
+use clap::Args;
+use dechst::backend::BackendWrite;
+use dechst::process::check::{Checker, ReadData};
+use dechst::repo::marker::LockMarker;
+use dechst::repo::DecryptedRepo;
+
+use crate::opts::{GlobalOpts, RepoOpts};
+use crate::util::lock_repo;
+
+#[derive(Debug, Args)]
+pub struct Opts {
+	/// Read and verify all packs
+	#[arg(long)]
+	read_data: bool,
+
+	/// Read and verify the nth of m subsets of all packs (e.g. `1/10`)
+	#[arg(long, value_name = "N/M", value_parser = parse_subset, conflicts_with = "read_data")]
+	read_data_subset: Option<ReadData>,
+}
+
+pub fn execute<B: BackendWrite>(
+	_: GlobalOpts,
+	repo_opts: RepoOpts,
+	cmd: Opts,
+	repo: DecryptedRepo<B>,
+) -> anyhow::Result<()> {
+	let Opts {
+		read_data,
+		read_data_subset,
+	} = cmd;
+
+	let read_data = match read_data_subset {
+		Some(subset) => subset,
+		None if read_data => ReadData::All,
+		None => ReadData::None,
+	};
+
+	let repo = lock_repo(repo, LockMarker::READ, &repo_opts)?;
+
+	let report = Checker::new()
+		.read_data(read_data)
+		.check(&repo)
+		.map_err(|err| anyhow::anyhow!("Failed to access the repository: {err}"))?;
+
+	println!(
+		"Checked {} snapshot(s), {} tree(s) and {} pack(s)",
+		report.snapshots, report.trees, report.packs
+	);
+
+	if read_data != ReadData::None {
+		println!(
+			"Read {} pack(s) with {} blob(s)",
+			report.packs_read, report.blobs_read
+		);
+	}
+
+	if !report.unindexed_packs.is_empty() {
+		println!(
+			"Found {} unindexed pack(s); Run prune to remove them",
+			report.unindexed_packs.len()
+		);
+	}
+
+	for problem in &report.problems {
+		println!("Error: {problem}");
+	}
+
+	if !report.is_ok() {
+		anyhow::bail!("Found {} problem(s)", report.problems.len());
+	}
+
+	println!("No problems found");
+
+	Ok(())
+}
+
+fn parse_subset(s: &str) -> Result<ReadData, String> {
+	let invalid = || format!("Invalid subset `{s}` (expected `N/M` with 1 <= N <= M)");
+
+	let (part, of) = s.split_once('/').ok_or_else(invalid)?;
+	let part: u32 = part.parse().map_err(|_| invalid())?;
+	let of: u32 = of.parse().map_err(|_| invalid())?;
+
+	if part == 0 || part > of {
+		return Err(invalid());
+	}
+
+	Ok(ReadData::Subset { part, of })
+}
@@ -0,0 +1,205 @@
+use chrono::{DateTime, Duration, Utc};
+use clap::Args;
+use dechst::backend::BackendWrite;
+use dechst::obj::lock::Exclusive;
+use dechst::process::forget::{self, Decision, RetentionPolicy};
+use dechst::repo::marker::LockMarker;
+use dechst::repo::snapshot::{SnapshotRead, SnapshotUpdate};
+use dechst::repo::DecryptedRepo;
+use serde::Serialize;
+
+use crate::format::OutputFormat;
+use crate::opts::{GlobalOpts, RepoOpts, SnapshotFilterOpts};
+use crate::util::lock_repo;
+
+#[derive(Debug, Args)]
+pub struct Opts {
+	#[command(flatten)]
+	filter: SnapshotFilterOpts,
+
+	/// Keep the last n snapshots
+	#[arg(long, value_name = "N", default_value_t)]
+	keep_last: u32,
+
+	/// Keep the newest snapshot of each of the last n hours
+	#[arg(long, value_name = "N", default_value_t)]
+	keep_hourly: u32,
+
+	/// Keep the newest snapshot of each of the last n days
+	#[arg(long, value_name = "N", default_value_t)]
+	keep_daily: u32,
+
+	/// Keep the newest snapshot of each of the last n weeks
+	#[arg(long, value_name = "N", default_value_t)]
+	keep_weekly: u32,
+
+	/// Keep the newest snapshot of each of the last n months
+	#[arg(long, value_name = "N", default_value_t)]
+	keep_monthly: u32,
+
+	/// Keep the newest snapshot of each of the last n years
+	#[arg(long, value_name = "N", default_value_t)]
+	keep_yearly: u32,
+
+	/// Keep all snapshots within the duration of the newest snapshot (e.g.
+	/// `1y6m`, `2w3d`, `12h`; a month is 30 days, a year 365 days)
+	#[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+	keep_within: Option<Duration>,
+
+	/// Keep all snapshots with the given tag(s)
+	#[arg(long = "keep-tag", value_name = "TAG")]
+	keep_tags: Vec<String>,
+
+	/// Only print which snapshots would be removed
+	#[arg(short = 'n', long)]
+	dry_run: bool,
+
+	#[arg(long, value_enum, default_value_t)]
+	format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+	id: String,
+	short_id: String,
+	time: DateTime<Utc>,
+	keep: bool,
+	reasons: Vec<String>,
+}
+
+impl From<&Decision> for Entry {
+	fn from(value: &Decision) -> Self {
+		let id = value.snapshot.id.to_string();
+
+		Self {
+			short_id: id[..8].to_string(),
+			id,
+			time: value.snapshot.value.time,
+			keep: value.keep(),
+			reasons: value.reasons.iter().map(ToString::to_string).collect(),
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+struct Group {
+	host: Option<String>,
+	root: String,
+	snapshots: Vec<Entry>,
+}
+
+pub fn execute<B: BackendWrite>(
+	_: GlobalOpts,
+	repo_opts: RepoOpts,
+	cmd: Opts,
+	repo: DecryptedRepo<B>,
+) -> anyhow::Result<()> {
+	let Opts {
+		filter,
+		keep_last,
+		keep_hourly,
+		keep_daily,
+		keep_weekly,
+		keep_monthly,
+		keep_yearly,
+		keep_within,
+		keep_tags,
+		dry_run,
+		format,
+	} = cmd;
+
+	let policy = RetentionPolicy {
+		last: keep_last,
+		hourly: keep_hourly,
+		daily: keep_daily,
+		weekly: keep_weekly,
+		monthly: keep_monthly,
+		yearly: keep_yearly,
+		within: keep_within,
+		tags: keep_tags,
+	};
+
+	if policy.is_empty() {
+		anyhow::bail!("No retention policy given; Refusing to remove all snapshots");
+	}
+
+	anyhow::ensure!(
+		dry_run || !repo_opts.append_only,
+		"Snapshots can not be removed from an append-only repository"
+	);
+
+	let mut repo = lock_repo(repo, LockMarker::READ.snapshot::<Exclusive>(), &repo_opts)?;
+
+	let mut snapshots = repo
+		.snapshots_read()
+		.map_err(|err| anyhow::anyhow!("Failed to read snapshots: {err}"))?;
+	snapshots.retain(|s| filter.matches(&s.value));
+
+	let decisions = policy.apply(snapshots);
+
+	let groups = decisions
+		.iter()
+		.map(|(group, decisions)| Group {
+			host: group.host.as_ref().map(ToString::to_string),
+			root: group.root.to_string(),
+			snapshots: decisions.iter().map(Entry::from).collect(),
+		})
+		.collect::<Vec<_>>();
+
+	format.print(&groups);
+
+	let (kept, removed) = forget::count(&decisions);
+
+	// Keep stdout to the formatted decisions
+	if dry_run {
+		eprintln!("Would keep {kept} and remove {removed} snapshot(s)");
+		return Ok(());
+	}
+
+	for decision in decisions.values().flatten().filter(|d| !d.keep()) {
+		let id = decision.snapshot.id;
+
+		repo.snapshot_remove(&id)
+			.map_err(|err| anyhow::anyhow!("Failed to remove snapshot {id}: {err}"))?;
+	}
+
+	eprintln!("Kept {kept} and removed {removed} snapshot(s)");
+
+	Ok(())
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+	let invalid = || format!("Invalid duration `{s}` (expected e.g. `1y6m`, `2w3d` or `12h`)");
+
+	let mut duration = Duration::zero();
+	let mut number = String::new();
+
+	for c in s.chars() {
+		if c.is_ascii_digit() {
+			number.push(c);
+			continue;
+		}
+
+		let n: i64 = number.parse().map_err(|_| invalid())?;
+		number.clear();
+
+		let part = match c {
+			'h' => Duration::try_hours(n),
+			'd' => Duration::try_days(n),
+			'w' => Duration::try_weeks(n),
+			'm' => n.checked_mul(30).and_then(Duration::try_days),
+			'y' => n.checked_mul(365).and_then(Duration::try_days),
+			_ => None,
+		};
+
+		duration = part
+			.and_then(|part| duration.checked_add(&part))
+			.ok_or_else(invalid)?;
+	}
+
+	if !number.is_empty() || s.is_empty() {
+		return Err(invalid());
+	}
+
+	Ok(duration)
+}
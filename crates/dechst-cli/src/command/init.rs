@@ -1,9 +1,10 @@
 use clap::Args;
+use dechst::backend::layout::{Layout, Sharding};
 use dechst::backend::BackendWrite;
 use dechst::id::Id;
 use dechst::obj::config::Config;
 use dechst::obj::key::{EncryptOptions, Key};
-use dechst::obj::ObjectKind;
+use dechst::obj::{ObjectKind, DIRECTORY_OBJECTS};
 use dechst::process::encrypt::EncryptionParams;
 use dechst::process::format::{Format, Formatter};
 use dechst::process::identify::Identify;
@@ -19,6 +20,57 @@ use crate::DEFAULT_PASSWORD;
 pub struct Opts {
 	#[command(flatten, next_help_heading = "PROCESS OPTIONS")]
 	process: ProcessOpts,
+
+	/// Splits the objects of a kind into nested directories by the given
+	/// number of hex characters per level (e.g. `packs=2,2` or `snapshots=`
+	/// to disable it; Defaults to `packs=2`)
+	#[arg(long, value_name = "KIND=LEVELS", value_parser = parse_sharding)]
+	sharding: Vec<(ObjectKind, Sharding)>,
+}
+
+impl Opts {
+	/// Returns the layout of the repository to create.
+	pub fn layout(&self) -> Layout {
+		self.sharding
+			.iter()
+			.fold(Layout::default(), |layout, (kind, sharding)| {
+				layout.with_sharding(*kind, sharding.clone())
+			})
+	}
+}
+
+fn parse_sharding(s: &str) -> Result<(ObjectKind, Sharding), String> {
+	let (kind, levels) = s
+		.split_once('=')
+		.ok_or_else(|| format!("Invalid sharding `{s}` (expected e.g. `packs=2,2`)"))?;
+
+	let kind = *DIRECTORY_OBJECTS
+		.iter()
+		.find(|k| k.name() == kind)
+		.ok_or_else(|| {
+			let kinds: Vec<_> = DIRECTORY_OBJECTS.iter().map(ObjectKind::name).collect();
+			format!(
+				"Unknown kind `{kind}` (expected one of {})",
+				kinds.join(", ")
+			)
+		})?;
+
+	let levels = levels
+		.split(',')
+		.filter(|level| !level.is_empty())
+		.map(str::parse)
+		.collect::<Result<Vec<u8>, _>>()
+		.map_err(|err| format!("Invalid levels `{levels}`: {err}"))?;
+
+	let sharding = Sharding::new(levels).ok_or_else(|| {
+		format!(
+			"At most {} levels of 1 to {} characters are allowed",
+			Sharding::MAX_LEVELS,
+			Sharding::MAX_LEVEL_LEN
+		)
+	})?;
+
+	Ok((kind, sharding))
 }
 
 // TODO: Maybe move creation process into lib
@@ -36,7 +88,7 @@ pub fn execute<B: BackendWrite>(
 	}
 
 	// Prepare
-	let Opts { mut process } = cmd;
+	let Opts { mut process, .. } = cmd;
 	process.merge(ProcessOpts::recommended());
 
 	let encryption = process.chunk.encryption.unwrap();
@@ -66,7 +118,9 @@ pub fn execute<B: BackendWrite>(
 
 	// Write files
 
-	backend.create().unwrap();
+	backend
+		.create()
+		.map_err(|err| anyhow::anyhow!("Failed to create the repository: {err}"))?;
 
 	// Write key
 	{
@@ -82,7 +136,9 @@ pub fn execute<B: BackendWrite>(
 
 		let id = identifier.identify(&key, &bytes)?;
 
-		backend.write_all(ObjectKind::Key, &id, &bytes).unwrap();
+		backend
+			.write_all(ObjectKind::Key, &id, &bytes)
+			.map_err(|err| anyhow::anyhow!("Failed to write the key: {err}"))?;
 	}
 
 	// Write config
@@ -95,7 +151,7 @@ pub fn execute<B: BackendWrite>(
 
 		backend
 			.write_all(ObjectKind::Config, &Id::ZERO, &bytes)
-			.unwrap();
+			.map_err(|err| anyhow::anyhow!("Failed to write the config: {err}"))?;
 	}
 
 	Ok(())
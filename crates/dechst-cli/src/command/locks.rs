@@ -0,0 +1,79 @@
+use chrono::{DateTime, Utc};
+use clap::Args;
+use dechst::backend::BackendWrite;
+use dechst::id::Id;
+use dechst::obj::lock::{Lock, LockState};
+use dechst::repo::lock::LockRead;
+use dechst::repo::DecryptedRepo;
+use serde::Serialize;
+
+use crate::format::OutputFormat;
+use crate::opts::{GlobalOpts, RepoOpts};
+
+#[derive(Debug, Args)]
+pub struct Opts {
+	#[arg(long, value_enum, default_value_t)]
+	format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+	id: String,
+	short_id: String,
+	host: Option<String>,
+	user: Option<String>,
+	pid: u32,
+	created: DateTime<Utc>,
+	refreshed: Option<DateTime<Utc>>,
+	stale: bool,
+	state: LockState,
+}
+
+impl Summary {
+	fn new(id: Id, lock: &Lock) -> Self {
+		let id = id.to_string();
+
+		Self {
+			short_id: id[..8].to_string(),
+			id,
+			host: lock.meta.user.hostname().map(ToString::to_string),
+			user: lock.meta.user.username().map(ToString::to_string),
+			pid: lock.meta.pid,
+			created: lock.meta.created,
+			refreshed: lock.meta.refreshed,
+			stale: lock.meta.is_stale(),
+			state: lock.state,
+		}
+	}
+}
+
+pub fn execute<B: BackendWrite>(
+	_: GlobalOpts,
+	_: RepoOpts,
+	cmd: Opts,
+	repo: DecryptedRepo<B>,
+) -> anyhow::Result<()> {
+	let Opts { format } = cmd;
+
+	// Listing locks must work while the repository is locked, so no lock is
+	// taken here
+	let mut locks = Vec::new();
+
+	for id in repo
+		.locks()
+		.map_err(|err| anyhow::anyhow!("Failed to list locks: {err}"))?
+	{
+		let id = id.map_err(|err| anyhow::anyhow!("Failed to list locks: {err}"))?;
+
+		match repo.lock_read(&id) {
+			Ok(lock) => locks.push(Summary::new(id, &lock)),
+			Err(err) => log::warn!("Failed to read lock {id}: {err}"),
+		}
+	}
+
+	locks.sort_by_key(|l| l.created);
+
+	format.print(&locks);
+
+	Ok(())
+}
@@ -1,23 +1,44 @@
+pub mod backup;
 pub mod cat;
+pub mod check;
 #[cfg(feature = "clap_complete")]
 pub mod completions;
+pub mod forget;
 pub mod init;
 pub mod list;
+pub mod locks;
 #[cfg(feature = "clap_mangen")]
 pub mod man;
 #[cfg(feature = "clap-markdown")]
 pub mod md;
+pub mod prune;
+pub mod restore;
 #[cfg(feature = "self_update")]
 pub mod selfupdate;
+pub mod snapshots;
+pub mod unlock;
 
 use clap::Subcommand;
+use dechst::backend::command::{self, Program};
 use dechst::backend::local::Local;
-use dechst::backend::local2::Local2;
+use dechst::backend::memory::{self, Memory};
+#[cfg(feature = "backend-rest")]
+use dechst::backend::rest::{Rest, URL_PREFIX as REST_URL_PREFIX};
+#[cfg(feature = "backend-s3")]
+use dechst::backend::s3::{Credentials, S3, URL_PREFIX as S3_URL_PREFIX};
+use dechst::backend::sftp::{self, Sftp};
 use dechst::backend::BackendWrite;
 use dechst::repo::Repo;
 
 use crate::opts::Opts;
-use crate::util::unlock_repo;
+use crate::util::{default_cache_dir, unlock_repo};
+
+// Recognized even if the backend is not compiled in, to not mistake the url
+// for a local path
+#[cfg(not(feature = "backend-rest"))]
+const REST_URL_PREFIX: &str = "rest:";
+#[cfg(not(feature = "backend-s3"))]
+const S3_URL_PREFIX: &str = "s3:";
 
 #[non_exhaustive]
 #[derive(Debug, Subcommand)]
@@ -34,10 +55,18 @@ pub enum Command {
 
 	// Read
 	Cat(cat::Opts),
+	Check(check::Opts),
 	List(list::Opts),
+	Locks(locks::Opts),
+	Snapshots(snapshots::Opts),
 
 	// Write
+	Backup(backup::Opts),
+	Forget(forget::Opts),
 	Init(init::Opts),
+	Prune(prune::Opts),
+	Restore(restore::Opts),
+	Unlock(unlock::Opts),
 }
 
 pub fn execute(opts: Opts) -> anyhow::Result<()> {
@@ -64,12 +93,34 @@ pub fn execute(opts: Opts) -> anyhow::Result<()> {
 	}
 
 	if let Some(repo) = &opts.repo.repo {
-		if let Some(path) = repo.strip_prefix("v2://") {
-			let backend = Local2::new(path);
+		if repo.starts_with(memory::MOUNT_POINT) {
+			log::debug!("Repo is in memory; Nothing will be persisted");
+			let backend = Memory::new();
+			exec_repo_command(opts, backend)
+		} else if let Some(url) = repo.strip_prefix(REST_URL_PREFIX) {
+			let url = url.to_string();
+			exec_rest_command(opts, url)
+		} else if let Some(url) = repo.strip_prefix(S3_URL_PREFIX) {
+			let url = url.to_string();
+			exec_s3_command(opts, url)
+		} else if let Some(command) = repo.strip_prefix(command::URL_PREFIX) {
+			let backend = Program::spawn(command)
+				.map_err(|err| anyhow::anyhow!("Failed to start the program: {err}"))?;
+			exec_repo_command(opts, backend)
+		} else if repo.starts_with(sftp::URL_PREFIX) {
+			let backend = Sftp::connect(repo)
+				.map_err(|err| anyhow::anyhow!("Failed to connect: {err}"))?;
+			let backend = match &opts.command {
+				Command::Init(cmd) => backend.with_layout(cmd.layout()),
+				_ => backend,
+			};
 			exec_repo_command(opts, backend)
 		} else {
 			log::debug!("Repo is no url; Falling back to local");
-			let backend = Local::new(repo);
+			let backend = match &opts.command {
+				Command::Init(cmd) => Local::with_layout(repo, cmd.layout()),
+				_ => Local::new(repo),
+			};
 			exec_repo_command(opts, backend)
 		}
 	} else {
@@ -77,6 +128,37 @@ pub fn execute(opts: Opts) -> anyhow::Result<()> {
 	}
 }
 
+#[cfg(feature = "backend-rest")]
+fn exec_rest_command(opts: Opts, url: String) -> anyhow::Result<()> {
+	let backend = Rest::new(url).map_err(|err| anyhow::anyhow!("Invalid rest url: {err}"))?;
+	exec_repo_command(opts, backend)
+}
+
+#[cfg(not(feature = "backend-rest"))]
+fn exec_rest_command(_: Opts, _: String) -> anyhow::Result<()> {
+	anyhow::bail!("The rest backend is not compiled in (feature `backend-rest`)");
+}
+
+#[cfg(feature = "backend-s3")]
+fn exec_s3_command(opts: Opts, url: String) -> anyhow::Result<()> {
+	let credentials = Credentials::from_env()
+		.ok_or_else(|| anyhow::anyhow!("Missing `AWS_ACCESS_KEY_ID` or `AWS_SECRET_ACCESS_KEY`"))?;
+
+	let mut backend =
+		S3::from_url(url, credentials).map_err(|err| anyhow::anyhow!("Invalid s3 url: {err}"))?;
+	if let Ok(region) = std::env::var("AWS_DEFAULT_REGION").or_else(|_| std::env::var("AWS_REGION"))
+	{
+		backend = backend.region(region);
+	}
+
+	exec_repo_command(opts, backend)
+}
+
+#[cfg(not(feature = "backend-s3"))]
+fn exec_s3_command(_: Opts, _: String) -> anyhow::Result<()> {
+	anyhow::bail!("The s3 backend is not compiled in (feature `backend-s3`)");
+}
+
 fn exec_repo_command<B: BackendWrite>(opts: Opts, backend: B) -> anyhow::Result<()> {
 	let Opts {
 		global: global_opts,
@@ -93,12 +175,31 @@ fn exec_repo_command<B: BackendWrite>(opts: Opts, backend: B) -> anyhow::Result<
 		return list::execute(global_opts, repo_opts, cmd, backend);
 	}
 
-	let repo = Repo::open(backend).unwrap();
+	let repo = Repo::open(backend)
+		.map_err(|err| anyhow::anyhow!("Failed to open the repository: {err}"))?;
 
 	let repo = unlock_repo(repo, &repo_opts).map_err(|(_, err)| err)?;
 
+	let cache_dir = if repo_opts.no_cache {
+		None
+	} else {
+		repo_opts.cache_dir.clone().or_else(default_cache_dir)
+	};
+
+	let repo = repo
+		.cached(cache_dir)
+		.map_err(|err| anyhow::anyhow!("Failed to open the cache: {err}"))?;
+
 	match command {
+		Command::Backup(cmd) => backup::execute(global_opts, repo_opts, cmd, repo),
 		Command::Cat(cmd) => cat::execute(global_opts, repo_opts, cmd, repo),
+		Command::Check(cmd) => check::execute(global_opts, repo_opts, cmd, repo),
+		Command::Forget(cmd) => forget::execute(global_opts, repo_opts, cmd, repo),
+		Command::Locks(cmd) => locks::execute(global_opts, repo_opts, cmd, repo),
+		Command::Prune(cmd) => prune::execute(global_opts, repo_opts, cmd, repo),
+		Command::Restore(cmd) => restore::execute(global_opts, repo_opts, cmd, repo),
+		Command::Snapshots(cmd) => snapshots::execute(global_opts, repo_opts, cmd, repo),
+		Command::Unlock(cmd) => unlock::execute(global_opts, repo_opts, cmd, repo),
 		_ => anyhow::bail!("Unknown command: {command:?}"),
 	}
 }
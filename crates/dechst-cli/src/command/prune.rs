@@ -0,0 +1,77 @@
+use clap::Args;
+use dechst::backend::BackendWrite;
+use dechst::obj::lock::Exclusive;
+use dechst::process::prune::Pruner;
+use dechst::repo::marker::LockMarker;
+use dechst::repo::DecryptedRepo;
+
+use crate::opts::{GlobalOpts, RepoOpts};
+use crate::util::lock_repo;
+
+#[derive(Debug, Args)]
+pub struct Opts {
+	/// Repack packs of which more than the given percentage is unused
+	#[arg(long, value_name = "PERCENT", default_value_t = Pruner::DEFAULT_MAX_WASTE, value_parser = clap::value_parser!(u8).range(0..=100))]
+	max_waste: u8,
+
+	/// Only print what would be removed
+	#[arg(short = 'n', long)]
+	dry_run: bool,
+}
+
+pub fn execute<B: BackendWrite>(
+	_: GlobalOpts,
+	repo_opts: RepoOpts,
+	cmd: Opts,
+	repo: DecryptedRepo<B>,
+) -> anyhow::Result<()> {
+	let Opts { max_waste, dry_run } = cmd;
+
+	anyhow::ensure!(
+		dry_run || !repo_opts.append_only,
+		"Packs can not be removed from an append-only repository"
+	);
+
+	let marker = LockMarker::READ
+		.index::<Exclusive>()
+		.snapshot::<Exclusive>()
+		.pack::<Exclusive>();
+
+	let mut repo = lock_repo(repo, marker, &repo_opts)?;
+
+	let stats = Pruner::new()
+		.max_waste(max_waste)
+		.dry_run(dry_run)
+		.prune(&mut repo)?;
+
+	println!(
+		"Used blobs {}, unused blobs {}",
+		stats.used_blobs, stats.unused_blobs
+	);
+
+	let verb = if dry_run { "Would remove" } else { "Removed" };
+	println!(
+		"{verb} {} pack(s), repack {} pack(s) and remove {} orphaned pack(s)",
+		stats.removed_packs, stats.repacked_packs, stats.orphaned_packs
+	);
+	println!(
+		"{verb} {} bytes, repacking {} bytes into {} new pack(s)",
+		stats.removed_bytes, stats.repacked_bytes, stats.written_packs
+	);
+
+	if stats.recent_packs > 0 {
+		println!(
+			"Kept {} recent pack(s) which are not indexed yet",
+			stats.recent_packs
+		);
+	}
+
+	if stats.removed_leftovers > 0 {
+		println!(
+			"Removed {} leftover(s) of interrupted writes",
+			stats.removed_leftovers
+		);
+	}
+
+	Ok(())
+}
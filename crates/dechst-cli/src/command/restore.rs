@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use dechst::backend::ext::Find;
+use dechst::backend::BackendWrite;
+use dechst::id::Id;
+use dechst::process::restore::Restorer;
+use dechst::repo::marker::LockMarker;
+use dechst::repo::snapshot::SnapshotRead;
+use dechst::repo::DecryptedRepo;
+use dechst::target::fs::FsTarget;
+use dechst::target::RestoreMode;
+
+use crate::opts::{GlobalOpts, RepoOpts};
+use crate::util::lock_repo;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mode {
+	/// Only create new files but dont touch existing ones
+	#[default]
+	OnlyNew,
+	/// Only update existing files but dont create new ones
+	OnlyExisting,
+	/// Restore all files and delete any files not included in the snapshot
+	Clean,
+}
+
+impl From<Mode> for RestoreMode {
+	fn from(value: Mode) -> Self {
+		match value {
+			Mode::OnlyNew => Self::OnlyNew,
+			Mode::OnlyExisting => Self::OnlyExisting,
+			Mode::Clean => Self::Clean,
+		}
+	}
+}
+
+#[derive(Debug, Args)]
+pub struct Opts {
+	snapshot: String,
+
+	#[arg(short, long, value_hint = clap::ValueHint::DirPath)]
+	target: PathBuf,
+
+	#[arg(long, value_enum, default_value_t)]
+	mode: Mode,
+}
+
+pub fn execute<B: BackendWrite>(
+	_: GlobalOpts,
+	repo_opts: RepoOpts,
+	cmd: Opts,
+	repo: DecryptedRepo<B>,
+) -> anyhow::Result<()> {
+	let Opts {
+		snapshot,
+		target,
+		mode,
+	} = cmd;
+
+	let repo = lock_repo(repo, LockMarker::READ, &repo_opts)?;
+
+	let id = resolve_snapshot(&repo, &snapshot)?;
+	let snapshot = repo
+		.snapshot_read(&id)
+		.map_err(|err| anyhow::anyhow!("Failed to read snapshot {id}: {err}"))?;
+
+	println!("Restoring snapshot {id} to {}", target.display());
+
+	let stats = Restorer::new(mode.into()).restore(&repo, &snapshot, &FsTarget::new(target))?;
+
+	println!(
+		"Restored {}, skipped {}, removed {}, failed {}",
+		stats.restored, stats.skipped, stats.removed, stats.failed
+	);
+
+	if stats.failed > 0 {
+		anyhow::bail!("Failed to restore {} item(s)", stats.failed);
+	}
+
+	Ok(())
+}
+
+fn resolve_snapshot<R: SnapshotRead>(repo: &R, id: &str) -> anyhow::Result<Id> {
+	let find = repo
+		.snapshot_find(id)
+		.map_err(|err| anyhow::anyhow!("Failed to find snapshot {id}: {err}"))?;
+
+	match find {
+		Some(Find::Unique(id)) => Ok(id),
+		Some(Find::NonUnique) => anyhow::bail!("Multiple snapshots found for the given id"),
+		_ => anyhow::bail!("No snapshot found for the given id"),
+	}
+}
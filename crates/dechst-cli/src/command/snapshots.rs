@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use dechst::backend::BackendWrite;
+use dechst::id::Idd;
+use dechst::obj::snapshot::{Snapshot, SnapshotGroup};
+use dechst::repo::marker::LockMarker;
+use dechst::repo::snapshot::SnapshotRead;
+use dechst::repo::DecryptedRepo;
+use serde::Serialize;
+
+use crate::format::OutputFormat;
+use crate::opts::{GlobalOpts, RepoOpts, SnapshotFilterOpts};
+use crate::util::lock_repo;
+
+#[derive(Debug, Args)]
+pub struct Opts {
+	#[command(flatten)]
+	filter: SnapshotFilterOpts,
+
+	/// Group snapshots by host and root path
+	#[arg(long)]
+	group: bool,
+
+	#[arg(long, value_enum, default_value_t)]
+	format: OutputFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+	id: String,
+	short_id: String,
+	time: DateTime<Utc>,
+	host: Option<String>,
+	user: Option<String>,
+	root: String,
+	tags: Vec<String>,
+	name: Option<String>,
+}
+
+impl From<&Idd<Snapshot>> for Summary {
+	fn from(value: &Idd<Snapshot>) -> Self {
+		let Idd { id, value } = value;
+		let id = id.to_string();
+
+		Self {
+			short_id: id[..8].to_string(),
+			id,
+			time: value.time,
+			host: value.user.hostname().map(ToString::to_string),
+			user: value.user.username().map(ToString::to_string),
+			root: value.root.to_string(),
+			tags: value.tags.clone(),
+			name: value.name.clone(),
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+struct Group {
+	host: Option<String>,
+	root: String,
+	snapshots: Vec<Summary>,
+}
+
+pub fn execute<B: BackendWrite>(
+	_: GlobalOpts,
+	repo_opts: RepoOpts,
+	cmd: Opts,
+	repo: DecryptedRepo<B>,
+) -> anyhow::Result<()> {
+	let Opts {
+		filter,
+		group,
+		format,
+	} = cmd;
+
+	let repo = lock_repo(repo, LockMarker::READ, &repo_opts)?;
+
+	let mut snapshots = repo
+		.snapshots_read()
+		.map_err(|err| anyhow::anyhow!("Failed to read snapshots: {err}"))?;
+
+	snapshots.retain(|s| filter.matches(&s.value));
+	snapshots.sort_by_key(|s| s.value.time);
+
+	if group {
+		let mut groups: BTreeMap<SnapshotGroup, Vec<Summary>> = BTreeMap::new();
+
+		for snapshot in &snapshots {
+			groups
+				.entry(snapshot.value.group())
+				.or_default()
+				.push(snapshot.into());
+		}
+
+		let groups = groups
+			.into_iter()
+			.map(|(group, snapshots)| Group {
+				host: group.host.map(|h| h.to_string()),
+				root: group.root.to_string(),
+				snapshots,
+			})
+			.collect::<Vec<_>>();
+
+		format.print(&groups);
+	} else {
+		let snapshots = snapshots.iter().map(Summary::from).collect::<Vec<_>>();
+
+		format.print(&snapshots);
+	}
+
+	Ok(())
+}
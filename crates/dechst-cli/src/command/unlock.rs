@@ -0,0 +1,61 @@
+use clap::Args;
+use dechst::backend::BackendWrite;
+use dechst::repo::lock::{LockRead, LockUpdate};
+use dechst::repo::DecryptedRepo;
+
+use crate::opts::{GlobalOpts, RepoOpts};
+
+#[derive(Debug, Args)]
+pub struct Opts {
+	/// Remove all locks instead of only stale ones
+	///
+	/// WARNING: Removing the lock of a running process can corrupt the
+	/// repository
+	#[arg(long)]
+	all: bool,
+}
+
+pub fn execute<B: BackendWrite>(
+	_: GlobalOpts,
+	repo_opts: RepoOpts,
+	cmd: Opts,
+	mut repo: DecryptedRepo<B>,
+) -> anyhow::Result<()> {
+	let Opts { all } = cmd;
+
+	anyhow::ensure!(
+		!repo_opts.append_only,
+		"Locks can not be removed from an append-only repository"
+	);
+
+	let ids = repo
+		.locks()
+		.map_err(|err| anyhow::anyhow!("Failed to list locks: {err}"))?
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|err| anyhow::anyhow!("Failed to list locks: {err}"))?;
+
+	let mut removed = 0;
+
+	for id in ids {
+		if !all {
+			match repo.lock_read(&id) {
+				Ok(lock) if lock.meta.is_stale() => {
+					log::info!("Removing stale lock {id} of {}", lock.meta);
+				}
+				Ok(_) => continue,
+				Err(err) => {
+					log::warn!("Failed to read lock {id}: {err}");
+					continue;
+				}
+			}
+		}
+
+		repo.lock_remove(&id)
+			.map_err(|err| anyhow::anyhow!("Failed to remove lock {id}: {err}"))?;
+		removed += 1;
+	}
+
+	println!("Removed {removed} lock(s)");
+
+	Ok(())
+}
@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use clap::Args;
+use dechst::obj::snapshot::Snapshot;
+
+#[derive(Default, Debug, Clone, Args)]
+pub struct SnapshotFilterOpts {
+	/// Only include snapshots of the given host(s)
+	#[arg(long = "host")]
+	pub hosts: Vec<String>,
+
+	/// Only include snapshots which have all given tag(s)
+	#[arg(long = "tag")]
+	pub tags: Vec<String>,
+
+	/// Only include snapshots of the given root path(s)
+	#[arg(long = "path", value_hint = clap::ValueHint::AnyPath)]
+	pub paths: Vec<PathBuf>,
+
+	/// Only include snapshots created at or after the given time (RFC 3339 or
+	/// YYYY-MM-DD)
+	#[arg(long, value_parser = parse_time)]
+	pub since: Option<DateTime<Utc>>,
+
+	/// Only include snapshots created before the given time (RFC 3339 or
+	/// YYYY-MM-DD)
+	#[arg(long, value_parser = parse_time)]
+	pub until: Option<DateTime<Utc>>,
+}
+
+impl SnapshotFilterOpts {
+	pub fn matches(&self, snapshot: &Snapshot) -> bool {
+		if !self.hosts.is_empty() {
+			let Some(host) = snapshot.user.hostname() else {
+				return false;
+			};
+
+			if !self.hosts.contains(&host.to_string()) {
+				return false;
+			}
+		}
+
+		if !self.tags.iter().all(|t| snapshot.tags.contains(t)) {
+			return false;
+		}
+
+		if !self.paths.is_empty() {
+			let root = snapshot.root.to_os_str();
+			if !self.paths.iter().any(|p| p.as_os_str() == root) {
+				return false;
+			}
+		}
+
+		if matches!(self.since, Some(since) if snapshot.time < since) {
+			return false;
+		}
+
+		if matches!(self.until, Some(until) if snapshot.time >= until) {
+			return false;
+		}
+
+		true
+	}
+}
+
+fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+	if let Ok(time) = s.parse::<DateTime<Utc>>() {
+		return Ok(time);
+	}
+
+	let date = s
+		.parse::<NaiveDate>()
+		.map_err(|_| format!("Invalid time `{s}` (expected RFC 3339 or YYYY-MM-DD)"))?;
+
+	Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))
+}
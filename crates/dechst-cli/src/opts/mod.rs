@@ -1,8 +1,10 @@
+pub mod filter;
 pub mod global;
 pub mod process;
 pub mod repo;
 
 use clap::Parser;
+pub use filter::SnapshotFilterOpts;
 pub use global::GlobalOpts;
 pub use process::{ChunkProcessOpts, ProcessOpts, RepoProcessOpts};
 pub use repo::RepoOpts;
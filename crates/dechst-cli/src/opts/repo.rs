@@ -8,6 +8,11 @@ use zeroize::Zeroizing;
 #[derive(Default, Debug, Args, Serialize, Deserialize, Merge)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct RepoOpts {
+	/// Location of the repository (a path, `rest:<url>` for a rest-server,
+	/// `s3:<url>/<bucket>/<prefix>` for S3, `sftp://[user@]host/<path>` for
+	/// SSH servers, `command:<program> <args>` for a storage program or
+	/// `mem://` for a temporary repository which only lives as long as the
+	/// command)
 	#[arg(short, long, global = true, env = "DECHST_REPO", value_hint = clap::ValueHint::DirPath)]
 	pub repo: Option<String>,
 
@@ -43,6 +48,7 @@ pub struct RepoOpts {
 	#[merge(strategy = merge::bool::overwrite_false)]
 	pub no_password: bool,
 
+	/// Do not cache snapshots and indices locally
 	#[arg(
 		long,
 		global = true,
@@ -52,6 +58,8 @@ pub struct RepoOpts {
 	#[merge(strategy = merge::bool::overwrite_false)]
 	pub no_cache: bool,
 
+	/// Directory of the local caches (defaults to the user's cache
+	/// directory)
 	#[arg(
 		long,
 		global = true,
@@ -61,6 +69,14 @@ pub struct RepoOpts {
 	)]
 	pub cache_dir: Option<PathBuf>,
 
+	/// Do not write locks and never remove objects (for storage which rejects
+	/// deletes)
+	///
+	/// WARNING: Other clients can not see that the repository is in use
+	#[arg(long, global = true, env = "DECHST_APPEND_ONLY")]
+	#[merge(strategy = merge::bool::overwrite_false)]
+	pub append_only: bool,
+
 	#[arg(short, long, global = true, env = "DECHST_KEY")]
 	pub key: Option<Zeroizing<String>>,
 }
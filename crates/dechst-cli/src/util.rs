@@ -1,7 +1,11 @@
+use std::path::PathBuf;
+
 use dechst::backend::ext::Find;
 use dechst::backend::BackendWrite;
 use dechst::id::Id;
-use dechst::repo::{DecryptedRepo, Repo};
+use dechst::obj::lock::LockState;
+use dechst::repo::marker::LockMarker;
+use dechst::repo::{DecryptedRepo, LockedRepo, Repo, RepoError};
 
 use crate::opts::RepoOpts;
 use crate::password::Password;
@@ -30,6 +34,25 @@ pub fn unlock_repo<B: BackendWrite>(
 	}
 }
 
+/// Locks `repo` with the access of `marker` or opens it without writing a lock
+/// if `--append-only` is given.
+pub fn lock_repo<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
+	repo: DecryptedRepo<B>,
+	marker: LockMarker<CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
+	opts: &RepoOpts,
+) -> anyhow::Result<LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>>
+where
+	LockMarker<CONFIG, INDEX, KEY, SNAPSHOT, PACK>: Into<LockState> + Copy,
+{
+	let repo = if opts.append_only {
+		repo.append_only(marker)
+	} else {
+		repo.lock(marker)
+	};
+
+	repo.map_err(|(_, err)| anyhow::anyhow!("Failed to lock the repository: {err}"))
+}
+
 pub fn try_unlock<B: BackendWrite>(
 	repo: Repo<B>,
 	key: Option<&str>,
@@ -65,32 +88,14 @@ pub fn try_unlock_encrypted_single<B: BackendWrite>(
 	};
 
 	repo.decrypt(key_id, password.as_bytes())
-		.map_err(|(repo, _)| (repo, anyhow::anyhow!("Failed to decrypt the repository")))
+		.map_err(|(repo, err)| (repo, decrypt_error(err)))
 }
 
 pub fn try_unlock_encrypted_all<B: BackendWrite>(
 	repo: Repo<B>,
 	password: Password,
 ) -> Result<DecryptedRepo<B>, (Repo<B>, anyhow::Error)> {
-	let keys = match repo.keys() {
-		Ok(keys) => keys,
-		Err(_) => return Err((repo, anyhow::anyhow!("Failed to list keys"))),
-	};
-
-	let mut r = repo;
-
-	for key in keys {
-		let Ok(key) =  key else {continue};
-
-		match r.decrypt(key, password.as_bytes()) {
-			Ok(repo) => return Ok(repo),
-			Err((repo, _)) => {
-				r = repo;
-			}
-		}
-	}
-
-	Err((r, anyhow::anyhow!("Failed to find key")))
+	try_unlock_all(repo, |repo, key| repo.decrypt(key, password.as_bytes()))
 }
 
 pub fn try_unlock_unencrypted<B: BackendWrite>(
@@ -114,31 +119,62 @@ pub fn try_unlock_unencrypted_single<B: BackendWrite>(
 	};
 
 	repo.try_unencrypted(key_id)
-		.map_err(|(repo, _)| (repo, anyhow::anyhow!("Failed to decrypt the repository")))
+		.map_err(|(repo, err)| (repo, decrypt_error(err)))
 }
 
 pub fn try_unlock_unencrypted_all<B: BackendWrite>(
 	repo: Repo<B>,
 ) -> Result<DecryptedRepo<B>, (Repo<B>, anyhow::Error)> {
+	try_unlock_all(repo, Repo::try_unencrypted)
+}
+
+/// Tries to unlock the repository with each key until one succeeds.
+///
+/// A wrong password only means that another key might match; Any other
+/// failure is reported if no key matches.
+fn try_unlock_all<B, F>(
+	repo: Repo<B>,
+	unlock: F,
+) -> Result<DecryptedRepo<B>, (Repo<B>, anyhow::Error)>
+where
+	B: BackendWrite,
+	F: Fn(Repo<B>, Id) -> Result<DecryptedRepo<B>, (Repo<B>, RepoError)>,
+{
 	let keys = match repo.keys() {
 		Ok(keys) => keys,
-		Err(_) => return Err((repo, anyhow::anyhow!("Failed to list keys"))),
+		Err(err) => return Err((repo, anyhow::anyhow!("Failed to list keys: {err}"))),
 	};
 
 	let mut r = repo;
+	let mut error = None;
 
 	for key in keys {
-		let Ok(key) =  key else {continue};
+		let key = match key {
+			Ok(key) => key,
+			Err(err) => return Err((r, anyhow::anyhow!("Failed to list keys: {err}"))),
+		};
 
-		match r.try_unencrypted(key) {
+		match unlock(r, key) {
 			Ok(repo) => return Ok(repo),
-			Err((repo, _)) => {
+			Err((repo, RepoError::WrongPassword)) => r = repo,
+			Err((repo, err)) => {
+				log::warn!("Failed to unlock key {key}: {err}");
+				error.get_or_insert(err);
 				r = repo;
 			}
 		}
 	}
 
-	Err((r, anyhow::anyhow!("Failed to find key")))
+	let err = match error {
+		Some(err) => anyhow::anyhow!("Failed to find a matching key: {err}"),
+		None => anyhow::anyhow!("Wrong password; No key matches"),
+	};
+
+	Err((r, err))
+}
+
+fn decrypt_error(err: RepoError) -> anyhow::Error {
+	anyhow::anyhow!("Failed to decrypt the repository: {err}")
 }
 
 fn get_key_id<B: BackendWrite>(repo: &Repo<B>, key: &str) -> anyhow::Result<Id> {
@@ -146,6 +182,20 @@ fn get_key_id<B: BackendWrite>(repo: &Repo<B>, key: &str) -> anyhow::Result<Id>
 		Ok(Some(Find::Unique(id))) => Ok(id),
 		Ok(Some(Find::NonUnique)) => anyhow::bail!("Multiple matching keys found"),
 		Ok(_) => anyhow::bail!("No matching key found"),
-		Err(err) => anyhow::bail!("Failed to retrive keys"),
+		Err(err) => Err(anyhow::anyhow!("Failed to retrieve keys: {err}")),
 	}
 }
+
+/// Returns the directory in which the caches of all repositories are kept.
+pub fn default_cache_dir() -> Option<PathBuf> {
+	let base = if cfg!(windows) {
+		std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+	} else {
+		std::env::var_os("XDG_CACHE_HOME")
+			.filter(|dir| !dir.is_empty())
+			.map(PathBuf::from)
+			.or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+	};
+
+	base.map(|base| base.join("dechst"))
+}
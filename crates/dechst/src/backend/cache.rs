@@ -0,0 +1,273 @@
+//! Local copies of cacheable objects (see [`ObjectKind::is_cacheable`]).
+//!
+//! Objects are cached exactly as stored in the backend (e.g. still
+//! encrypted). Cached objects are evicted once they are no longer listed by
+//! the backend. Failures of the cache itself are only logged, as the backend
+//! can always be used instead.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read as _, Seek as _, SeekFrom};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::backend::local::{is_temp, write_atomic};
+use crate::backend::{BackendRead, BackendWrite, ObjectMetadata, Result};
+use crate::id::Id;
+use crate::obj::ObjectKind;
+
+#[derive(Debug, Clone)]
+pub struct Cache<B> {
+	backend: B,
+	/// Directory of the cache for this repository; `None` if disabled
+	path: Option<PathBuf>,
+}
+
+impl<B> Cache<B> {
+	/// Caches the objects of `backend` in the directory `path`.
+	///
+	/// The directory must only be used for a single repository.
+	pub fn new<P: Into<PathBuf>>(backend: B, path: P) -> Self {
+		Self {
+			backend,
+			path: Some(path.into()),
+		}
+	}
+
+	/// Passes all calls through to `backend`.
+	pub const fn disabled(backend: B) -> Self {
+		Self {
+			backend,
+			path: None,
+		}
+	}
+
+	pub const fn path(&self) -> Option<&PathBuf> {
+		self.path.as_ref()
+	}
+
+	pub fn into_inner(self) -> B {
+		self.backend
+	}
+
+	fn kind_path(&self, kind: ObjectKind) -> Option<PathBuf> {
+		if kind.is_cacheable() {
+			self.path.as_ref().map(|path| path.join(kind.name()))
+		} else {
+			None
+		}
+	}
+
+	fn object_path(&self, kind: ObjectKind, id: &Id) -> Option<PathBuf> {
+		self.kind_path(kind).map(|path| path.join(id.to_hex()))
+	}
+
+	/// Stores `bytes` as the object `id`.
+	fn insert(&self, kind: ObjectKind, id: &Id, bytes: &[u8]) {
+		if let Some(path) = self.object_path(kind, id) {
+			if let Err(err) = write_atomic(&path, bytes) {
+				log::warn!("Failed to cache {kind} {id}: {err}");
+			}
+		}
+	}
+
+	/// Removes the object `id`.
+	fn evict(&self, kind: ObjectKind, id: &Id) {
+		if let Some(path) = self.object_path(kind, id) {
+			match std::fs::remove_file(&path) {
+				Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+					log::warn!("Failed to evict {kind} {id} from the cache: {err}");
+				}
+				_ => {}
+			}
+		}
+	}
+
+	/// Removes all objects of kind `kind` which are not in `ids`.
+	fn retain(&self, kind: ObjectKind, ids: &HashSet<Id>) {
+		let Some(path) = self.kind_path(kind) else {
+			return;
+		};
+
+		let entries = match std::fs::read_dir(&path) {
+			Ok(entries) => entries,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return,
+			Err(err) => {
+				log::warn!("Failed to list the cache `{}`: {err}", path.display());
+				return;
+			}
+		};
+
+		for entry in entries.flatten() {
+			let name = entry.file_name();
+			if is_temp(&name) {
+				continue;
+			}
+
+			let keep = Id::from_str(&name.to_string_lossy()).is_ok_and(|id| ids.contains(&id));
+
+			if !keep {
+				log::debug!("Evicting {}", entry.path().display());
+				if let Err(err) = std::fs::remove_file(entry.path()) {
+					log::warn!("Failed to evict `{}`: {err}", entry.path().display());
+				}
+			}
+		}
+	}
+}
+
+impl<B: BackendRead> BackendRead for Cache<B> {
+	type Iter = std::vec::IntoIter<Result<Id>>;
+
+	fn mount_point(&self) -> Cow<'_, str> {
+		self.backend.mount_point()
+	}
+
+	fn verify(&self) -> Result<()> {
+		self.backend.verify()
+	}
+
+	fn iter(&self, kind: ObjectKind) -> Result<Self::Iter> {
+		let ids: Vec<_> = self.backend.iter(kind)?.collect();
+
+		// An incomplete listing must not evict anything
+		if let Ok(listed) = ids.iter().map(|id| id.as_ref().copied()).collect() {
+			self.retain(kind, &listed);
+		}
+
+		Ok(ids.into_iter())
+	}
+
+	fn exists(&self, kind: ObjectKind, id: &Id) -> Result<()> {
+		self.backend.exists(kind, id)
+	}
+
+	fn meta(&self, kind: ObjectKind, id: &Id) -> Result<ObjectMetadata> {
+		if let Some(meta) = self.object_path(kind, id).and_then(|p| p.metadata().ok()) {
+			return Ok(ObjectMetadata {
+				accessed: meta.accessed().ok().map(|t| t.into()),
+				created: meta.created().ok().map(|t| t.into()),
+				modified: meta.modified().ok().map(|t| t.into()),
+				len: meta.len(),
+			});
+		}
+
+		self.backend.meta(kind, id)
+	}
+
+	fn read_at(&self, kind: ObjectKind, id: &Id, offset: u32, buf: &mut [u8]) -> Result<usize> {
+		if let Some(path) = self.object_path(kind, id) {
+			let mut read = || -> std::io::Result<()> {
+				let mut r = File::open(&path)?;
+				r.seek(SeekFrom::Start(u64::from(offset)))?;
+				r.read_exact(buf)
+			};
+
+			if read().is_ok() {
+				return Ok(buf.len());
+			}
+		}
+
+		self.backend.read_at(kind, id, offset, buf)
+	}
+
+	fn read_all(&self, kind: ObjectKind, id: &Id, buf: &mut Vec<u8>) -> Result<usize> {
+		let Some(path) = self.object_path(kind, id) else {
+			return self.backend.read_all(kind, id, buf);
+		};
+
+		let start = buf.len();
+
+		match File::open(&path).and_then(|mut r| r.read_to_end(buf)) {
+			Ok(len) => {
+				log::trace!("Read {kind} {id} from the cache");
+				return Ok(len);
+			}
+			Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+				log::warn!("Failed to read {kind} {id} from the cache: {err}");
+				buf.truncate(start);
+			}
+			Err(_) => {}
+		}
+
+		let len = self.backend.read_all(kind, id, buf)?;
+		self.insert(kind, id, &buf[start..]);
+
+		Ok(len)
+	}
+}
+
+impl<B: BackendWrite> BackendWrite for Cache<B> {
+	fn create(&mut self) -> Result<()> {
+		self.backend.create()
+	}
+
+	fn remove(&mut self, kind: ObjectKind, id: &Id) -> Result<()> {
+		self.evict(kind, id);
+		self.backend.remove(kind, id)
+	}
+
+	fn write_all(&mut self, kind: ObjectKind, id: &Id, buf: &[u8]) -> Result<()> {
+		self.backend.write_all(kind, id, buf)?;
+		self.insert(kind, id, buf);
+
+		Ok(())
+	}
+
+	fn clean(&mut self, age: Duration) -> Result<u64> {
+		self.backend.clean(age)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::backend::ext::ReadToEnd;
+	use crate::backend::memory::Memory;
+
+	#[test]
+	fn cache() {
+		let path = std::env::temp_dir().join(format!("dechst-cache-{}", Id::random()));
+		let mut backend = Memory::new();
+		let mut cache = Cache::new(backend.clone(), &path);
+
+		let cached = Id::random();
+		let evicted = Id::random();
+		backend
+			.write_all(ObjectKind::Snapshot, &cached, b"cached")
+			.unwrap();
+		cache
+			.write_all(ObjectKind::Snapshot, &evicted, b"evicted")
+			.unwrap();
+		cache.write_all(ObjectKind::Pack, &cached, b"pack").unwrap();
+
+		assert_eq!(
+			cache.read_to_end(ObjectKind::Snapshot, &cached).unwrap(),
+			b"cached"
+		);
+
+		// Served from the cache from now on
+		backend
+			.write_all(ObjectKind::Snapshot, &cached, b"changed")
+			.unwrap();
+		assert_eq!(
+			cache.read_to_end(ObjectKind::Snapshot, &cached).unwrap(),
+			b"cached"
+		);
+
+		let snapshots = path.join(ObjectKind::Snapshot.name());
+		assert_eq!(std::fs::read_dir(&snapshots).unwrap().count(), 2);
+		assert!(!path.join(ObjectKind::Pack.name()).exists());
+
+		backend.remove(ObjectKind::Snapshot, &evicted).unwrap();
+		assert_eq!(cache.iter(ObjectKind::Snapshot).unwrap().count(), 1);
+		assert_eq!(std::fs::read_dir(&snapshots).unwrap().count(), 1);
+
+		cache.remove(ObjectKind::Snapshot, &cached).unwrap();
+		assert_eq!(std::fs::read_dir(&snapshots).unwrap().count(), 0);
+
+		std::fs::remove_dir_all(path).unwrap();
+	}
+}
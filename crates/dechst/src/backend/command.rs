@@ -0,0 +1,470 @@
+//! Repositories in storages provided by an external program.
+//!
+//! The program is spawned once and receives requests on its stdin and sends
+//! responses on its stdout (its stderr is passed through). Requests are
+//! handled one at a time.
+//!
+//! # Protocol
+//!
+//! A request is a single line of space separated words, the first being the
+//! name of the request. Only `write_all` is followed by a body of raw bytes,
+//! its length being the last word of the line.
+//!
+//! | Request                              | Body of the response                          |
+//! |--------------------------------------|-----------------------------------------------|
+//! | `create`                             | Empty                                         |
+//! | `exists <kind> <id>`                 | Empty                                         |
+//! | `meta <kind> <id>`                   | `<len> <modified>` (unix seconds or `-`)      |
+//! | `iter <kind>`                        | One hex id per line                           |
+//! | `read_at <kind> <id> <offset> <len>` | Exactly `<len>` bytes of the object           |
+//! | `read_all <kind> <id>`               | The object                                    |
+//! | `write_all <kind> <id> <len>`        | Empty                                         |
+//! | `remove <kind> <id>`                 | Empty                                         |
+//!
+//! `<kind>` is the directory name of the object kind (e.g. `snapshots`) and
+//! `<id>` the hex id of the object (zero for the config). Objects must be
+//! written atomically.
+//!
+//! A response is either the line `ok <len>` followed by a body of `<len>`
+//! bytes or the line `error <error> <message>`, where `<error>` is one of
+//! `not-found`, `permission-denied`, `already-exists`, `corrupt` or `other`.
+//!
+//! ```text
+//! > read_all snapshots 0a1b..
+//! < ok 4
+//! < abcd
+//! > exists packs 0a1b..
+//! < error not-found No such object
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{TimeZone, Utc};
+
+use crate::backend::{BackendError, BackendRead, BackendWrite, ObjectMetadata, Result};
+use crate::id::Id;
+use crate::obj::ObjectKind;
+
+/// Prefix of repository locations which are provided by a program, followed
+/// by the program and its arguments separated by whitespace (e.g.
+/// `command:my-storage --bucket backups`).
+pub const URL_PREFIX: &str = "command:";
+
+/// Pipes to the program.
+struct Channel {
+	reader: BufReader<Box<dyn Read + Send>>,
+	writer: Box<dyn Write + Send>,
+	child: Option<Child>,
+}
+
+impl fmt::Debug for Channel {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Channel")
+			.field("child", &self.child)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Response of the program to a request.
+enum Reply {
+	Ok(Vec<u8>),
+	Error { error: String, message: String },
+}
+
+impl Channel {
+	fn request(&mut self, line: &str, body: &[u8]) -> io::Result<Reply> {
+		self.writer.write_all(line.as_bytes())?;
+		self.writer.write_all(b"\n")?;
+		self.writer.write_all(body)?;
+		self.writer.flush()?;
+
+		let mut line = String::new();
+		if self.reader.read_line(&mut line)? == 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::ConnectionAborted,
+				"The program exited",
+			));
+		}
+
+		let invalid = || {
+			io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("Invalid response `{}`", line.trim_end()),
+			)
+		};
+
+		let mut words = line.trim_end().splitn(3, ' ');
+
+		match words.next() {
+			Some("ok") => {
+				let len = words
+					.next()
+					.and_then(|len| len.parse().ok())
+					.ok_or_else(invalid)?;
+
+				let mut body = vec![0u8; len];
+				self.reader.read_exact(&mut body)?;
+
+				Ok(Reply::Ok(body))
+			}
+			Some("error") => {
+				let error = words.next().ok_or_else(invalid)?.to_string();
+				let message = words.next().unwrap_or_default().to_string();
+
+				Ok(Reply::Error { error, message })
+			}
+			_ => Err(invalid()),
+		}
+	}
+}
+
+impl Drop for Channel {
+	fn drop(&mut self) {
+		if let Some(child) = &mut self.child {
+			// Closing stdin tells the program to exit
+			self.writer = Box::new(io::sink());
+
+			if let Err(err) = child.wait() {
+				log::warn!("Failed to wait for the storage program: {err}");
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+	/// Command line of the program
+	location: String,
+	channel: Arc<Mutex<Channel>>,
+}
+
+impl Program {
+	/// Spawns the program of the command line `command` (words separated by
+	/// whitespace).
+	pub fn spawn(command: &str) -> Result<Self> {
+		let io = |err| BackendError::io(command, err);
+
+		let mut words = command.split_whitespace();
+		let program = words.next().ok_or_else(|| {
+			io(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Missing program",
+			))
+		})?;
+
+		let mut child = Command::new(program)
+			.args(words)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.spawn()
+			.map_err(io)?;
+
+		let stdin = child.stdin.take().expect("Piped stdin");
+		let stdout = child.stdout.take().expect("Piped stdout");
+
+		Ok(Self::with_channel(
+			command.to_string(),
+			Box::new(stdout),
+			Box::new(stdin),
+			Some(child),
+		))
+	}
+
+	fn with_channel(
+		location: String,
+		reader: Box<dyn Read + Send>,
+		writer: Box<dyn Write + Send>,
+		child: Option<Child>,
+	) -> Self {
+		Self {
+			location,
+			channel: Arc::new(Mutex::new(Channel {
+				reader: BufReader::new(reader),
+				writer,
+				child,
+			})),
+		}
+	}
+
+	fn channel(&self) -> MutexGuard<'_, Channel> {
+		self.channel.lock().unwrap_or_else(|err| err.into_inner())
+	}
+
+	/// Sends a request for the object `id` and returns the body of the
+	/// response.
+	fn call(
+		&self,
+		kind: ObjectKind,
+		id: Option<&Id>,
+		request: &str,
+		body: &[u8],
+	) -> Result<Vec<u8>> {
+		let location = match id {
+			Some(id) if kind != ObjectKind::Config => {
+				format!("{}:{}/{id}", self.location, kind.name())
+			}
+			_ => format!("{}:{}", self.location, kind.name()),
+		};
+
+		let reply = self
+			.channel()
+			.request(request, body)
+			.map_err(|err| BackendError::Io {
+				location: location.clone(),
+				source: err,
+			})?;
+
+		match reply {
+			Reply::Ok(body) => Ok(body),
+			Reply::Error { error, message } => Err(match error.as_str() {
+				"not-found" => BackendError::NotFound(location),
+				"permission-denied" => BackendError::PermissionDenied(location),
+				"already-exists" => BackendError::AlreadyExists(location),
+				"corrupt" => BackendError::Corrupt(format!("`{location}`: {message}")),
+				_ => BackendError::Io {
+					location,
+					source: io::Error::other(message),
+				},
+			}),
+		}
+	}
+
+	fn corrupt<E: fmt::Display>(&self, request: &str, err: E) -> BackendError {
+		BackendError::Corrupt(format!(
+			"Invalid response of `{}` to `{request}`: {err}",
+			self.location
+		))
+	}
+}
+
+impl BackendRead for Program {
+	type Iter = std::vec::IntoIter<Result<Id>>;
+
+	fn mount_point(&self) -> Cow<'_, str> {
+		Cow::Borrowed(&self.location)
+	}
+
+	fn verify(&self) -> Result<()> {
+		self.exists(ObjectKind::Config, &Id::ZERO)
+	}
+
+	fn iter(&self, kind: ObjectKind) -> Result<Self::Iter> {
+		let request = format!("iter {}", kind.name());
+		let body = self.call(kind, None, &request, &[])?;
+		let body = String::from_utf8(body).map_err(|err| self.corrupt(&request, err))?;
+
+		let ids: Vec<_> = body
+			.lines()
+			.filter(|line| !line.is_empty())
+			.map(|line| line.parse().map_err(|err| self.corrupt(&request, err)))
+			.collect();
+
+		Ok(ids.into_iter())
+	}
+
+	fn exists(&self, kind: ObjectKind, id: &Id) -> Result<()> {
+		let request = format!("exists {} {id}", kind.name());
+		self.call(kind, Some(id), &request, &[])?;
+
+		Ok(())
+	}
+
+	fn meta(&self, kind: ObjectKind, id: &Id) -> Result<ObjectMetadata> {
+		let request = format!("meta {} {id}", kind.name());
+		let body = self.call(kind, Some(id), &request, &[])?;
+		let body = String::from_utf8_lossy(&body);
+
+		let mut words = body.split_whitespace();
+		let len = words
+			.next()
+			.and_then(|len| len.parse().ok())
+			.ok_or_else(|| self.corrupt(&request, "Missing length"))?;
+		let modified = words
+			.next()
+			.and_then(|secs| secs.parse().ok())
+			.and_then(|secs| Utc.timestamp_opt(secs, 0).single());
+
+		Ok(ObjectMetadata {
+			accessed: None,
+			created: None,
+			modified,
+			len,
+		})
+	}
+
+	fn read_at(&self, kind: ObjectKind, id: &Id, offset: u32, buf: &mut [u8]) -> Result<usize> {
+		let request = format!("read_at {} {id} {offset} {}", kind.name(), buf.len());
+		let body = self.call(kind, Some(id), &request, &[])?;
+
+		if body.len() != buf.len() {
+			return Err(self.corrupt(
+				&request,
+				format!("Expected {} bytes, got {}", buf.len(), body.len()),
+			));
+		}
+
+		buf.copy_from_slice(&body);
+
+		Ok(buf.len())
+	}
+
+	fn read_all(&self, kind: ObjectKind, id: &Id, buf: &mut Vec<u8>) -> Result<usize> {
+		let request = format!("read_all {} {id}", kind.name());
+		let body = self.call(kind, Some(id), &request, &[])?;
+
+		buf.extend_from_slice(&body);
+
+		Ok(body.len())
+	}
+}
+
+impl BackendWrite for Program {
+	fn create(&mut self) -> Result<()> {
+		self.call(ObjectKind::Config, None, "create", &[])?;
+
+		Ok(())
+	}
+
+	fn remove(&mut self, kind: ObjectKind, id: &Id) -> Result<()> {
+		let request = format!("remove {} {id}", kind.name());
+		self.call(kind, Some(id), &request, &[])?;
+
+		Ok(())
+	}
+
+	fn write_all(&mut self, kind: ObjectKind, id: &Id, buf: &[u8]) -> Result<()> {
+		let request = format!("write_all {} {id} {}", kind.name(), buf.len());
+		self.call(kind, Some(id), &request, buf)?;
+
+		Ok(())
+	}
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod test {
+	use std::os::unix::net::UnixStream;
+
+	use super::*;
+	use crate::backend::ext::ReadToEnd;
+	use crate::backend::memory::Memory;
+	use crate::obj::DIRECTORY_OBJECTS;
+
+	/// Reference implementation of the protocol storing the objects in
+	/// `backend`.
+	fn serve(mut backend: Memory, stream: UnixStream) {
+		let mut reader = BufReader::new(stream.try_clone().unwrap());
+		let mut writer = stream;
+
+		let kind = |name: &str| {
+			std::iter::once(&ObjectKind::Config)
+				.chain(DIRECTORY_OBJECTS)
+				.copied()
+				.find(|kind| kind.name() == name)
+				.unwrap()
+		};
+
+		let mut line = String::new();
+		while reader.read_line(&mut line).unwrap() > 0 {
+			let words: Vec<_> = line.split_whitespace().collect();
+			let id = |i: usize| words[i].parse::<Id>().unwrap();
+
+			let result = match words[0] {
+				"create" => backend.create().map(|_| Vec::new()),
+				"exists" => backend.exists(kind(words[1]), &id(2)).map(|_| Vec::new()),
+				"meta" => backend
+					.meta(kind(words[1]), &id(2))
+					.map(|meta| format!("{} -", meta.len).into_bytes()),
+				"iter" => backend.iter(kind(words[1])).map(|ids| {
+					ids.map(|id| format!("{}\n", id.unwrap()))
+						.collect::<String>()
+						.into_bytes()
+				}),
+				"read_at" => {
+					let mut buf = vec![0u8; words[4].parse().unwrap()];
+					backend
+						.read_at(kind(words[1]), &id(2), words[3].parse().unwrap(), &mut buf)
+						.map(|_| buf)
+				}
+				"read_all" => backend.read_to_end(kind(words[1]), &id(2)),
+				"write_all" => {
+					let mut buf = vec![0u8; words[3].parse().unwrap()];
+					reader.read_exact(&mut buf).unwrap();
+					backend
+						.write_all(kind(words[1]), &id(2), &buf)
+						.map(|_| Vec::new())
+				}
+				"remove" => backend.remove(kind(words[1]), &id(2)).map(|_| Vec::new()),
+				request => panic!("Unknown request {request}"),
+			};
+
+			match result {
+				Ok(body) => {
+					writeln!(writer, "ok {}", body.len()).unwrap();
+					writer.write_all(&body).unwrap();
+				}
+				Err(err) if err.is_not_found() => {
+					writeln!(writer, "error not-found {err}").unwrap();
+				}
+				Err(err) => writeln!(writer, "error other {err}").unwrap(),
+			}
+
+			line.clear();
+		}
+	}
+
+	#[test]
+	fn command() {
+		let (client, server) = UnixStream::pair().unwrap();
+		let storage = Memory::new();
+		let served = storage.clone();
+		std::thread::spawn(move || serve(served, server));
+
+		let mut backend = Program::with_channel(
+			"stub".to_string(),
+			Box::new(client.try_clone().unwrap()),
+			Box::new(client),
+			None,
+		);
+		assert!(backend.verify().unwrap_err().is_not_found());
+
+		backend.create().unwrap();
+		backend.verify().unwrap();
+
+		let id = Id::random();
+		backend
+			.write_all(ObjectKind::Pack, &id, b"0123456789")
+			.unwrap();
+		assert_eq!(storage.len(ObjectKind::Pack), 1);
+		assert_eq!(backend.meta(ObjectKind::Pack, &id).unwrap().len, 10);
+		assert_eq!(
+			backend.read_to_end(ObjectKind::Pack, &id).unwrap(),
+			b"0123456789"
+		);
+
+		let mut buf = [0u8; 4];
+		assert_eq!(
+			backend.read_at(ObjectKind::Pack, &id, 6, &mut buf).unwrap(),
+			4
+		);
+		assert_eq!(&buf, b"6789");
+		assert!(backend.read_at(ObjectKind::Pack, &id, 7, &mut buf).is_err());
+
+		let ids: Vec<_> = backend
+			.iter(ObjectKind::Pack)
+			.unwrap()
+			.collect::<Result<_>>()
+			.unwrap();
+		assert_eq!(ids, [id]);
+		assert_eq!(backend.iter(ObjectKind::Index).unwrap().count(), 0);
+
+		backend.remove(ObjectKind::Pack, &id).unwrap();
+		assert!(backend
+			.remove(ObjectKind::Pack, &id)
+			.unwrap_err()
+			.is_not_found());
+	}
+}
@@ -66,7 +66,7 @@ where
 
 		let mut buf = Vec::with_capacity(meta.len as usize);
 
-		let _bread = self.read_all(kind, id, &mut buf);
+		self.read_all(kind, id, &mut buf)?;
 
 		Ok(buf)
 	}
@@ -0,0 +1,163 @@
+//! Arrangement of the objects within a backend.
+//!
+//! The layout is stored unencrypted next to the config, as it is required to
+//! find any object (including the config and keys).
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::id::Id;
+use crate::obj::ObjectKind;
+
+pub const LAYOUT_NAME: &str = "layout";
+
+/// Splits the hex names of objects into nested directories.
+///
+/// Each level is the number of hex characters used for one directory (e.g.
+/// `[2, 2]` stores `0a1b2c..` as `0a/1b/0a1b2c..`).
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Sharding(Vec<u8>);
+
+impl Sharding {
+	pub const MAX_LEVELS: usize = 4;
+	pub const MAX_LEVEL_LEN: u8 = 4;
+
+	/// Returns `None` if there are more than [`Self::MAX_LEVELS`] levels or
+	/// if any level is not within `1..=MAX_LEVEL_LEN`.
+	pub fn new(levels: Vec<u8>) -> Option<Self> {
+		let sharding = Self(levels);
+
+		sharding.is_valid().then_some(sharding)
+	}
+
+	pub fn levels(&self) -> &[u8] {
+		&self.0
+	}
+
+	pub fn is_valid(&self) -> bool {
+		self.0.len() <= Self::MAX_LEVELS
+			&& self
+				.0
+				.iter()
+				.all(|&len| (1..=Self::MAX_LEVEL_LEN).contains(&len))
+	}
+
+	/// Returns the directories (from the outermost) for the hex name `hex`.
+	pub fn dirs<'a>(&self, hex: &'a str) -> Vec<&'a str> {
+		let mut start = 0;
+
+		self.0
+			.iter()
+			.map(|&len| {
+				let end = start + usize::from(len);
+				let dir = &hex[start..end];
+				start = end;
+				dir
+			})
+			.collect()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layout {
+	pub version: u32,
+	/// Sharding by directory name of the object kind; Kinds which are not
+	/// listed are not sharded
+	sharding: BTreeMap<String, Sharding>,
+}
+
+impl Default for Layout {
+	/// The layout used before it was configurable (only packs are sharded by
+	/// `[2]`).
+	fn default() -> Self {
+		Self::unsharded().with_sharding(ObjectKind::Pack, Sharding(vec![2]))
+	}
+}
+
+impl Layout {
+	pub const fn unsharded() -> Self {
+		Self {
+			version: 1,
+			sharding: BTreeMap::new(),
+		}
+	}
+
+	pub fn with_sharding(mut self, kind: ObjectKind, sharding: Sharding) -> Self {
+		if sharding.0.is_empty() {
+			self.sharding.remove(kind.name());
+		} else {
+			self.sharding.insert(kind.name().to_string(), sharding);
+		}
+
+		self
+	}
+
+	pub fn sharding(&self, kind: ObjectKind) -> &[u8] {
+		self.sharding
+			.get(kind.name())
+			.map_or(&[], |sharding| sharding.levels())
+	}
+
+	pub fn is_valid(&self) -> bool {
+		self.sharding.values().all(Sharding::is_valid)
+	}
+
+	/// Returns the path of the object relative to the root of the backend.
+	pub fn path(&self, kind: ObjectKind, id: &Id) -> PathBuf {
+		let mut path = PathBuf::from(kind.name());
+
+		if kind != ObjectKind::Config {
+			let hex = id.to_hex();
+
+			if let Some(sharding) = self.sharding.get(kind.name()) {
+				path.extend(sharding.dirs(&hex));
+			}
+
+			path.push(hex);
+		}
+
+		path
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn layout_paths() {
+		let id = Id::random();
+		let hex = id.to_hex();
+
+		let layout = Layout::default()
+			.with_sharding(ObjectKind::Snapshot, Sharding::new(vec![1, 3]).unwrap())
+			.with_sharding(ObjectKind::Pack, Sharding::default());
+
+		assert_eq!(
+			layout.path(ObjectKind::Snapshot, &id),
+			PathBuf::from("snapshots")
+				.join(&hex[0..1])
+				.join(&hex[1..4])
+				.join(&hex)
+		);
+		assert_eq!(
+			layout.path(ObjectKind::Pack, &id),
+			PathBuf::from("packs").join(&hex)
+		);
+		assert_eq!(
+			Layout::default().path(ObjectKind::Pack, &id),
+			PathBuf::from("packs").join(&hex[0..2]).join(&hex)
+		);
+		assert_eq!(
+			layout.path(ObjectKind::Config, &Id::ZERO),
+			PathBuf::from("config")
+		);
+
+		assert!(Sharding::new(vec![0]).is_none());
+		assert!(Sharding::new(vec![5]).is_none());
+		assert!(Sharding::new(vec![1; 5]).is_none());
+	}
+}
@@ -4,51 +4,156 @@ use std::fs::{File, OpenOptions};
 use std::io::{Read as _, Seek as _, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 use walkdir::WalkDir;
 
-use crate::backend::{BackendRead, BackendWrite, ObjectMetadata, Result};
+use crate::backend::layout::{Layout, LAYOUT_NAME};
+use crate::backend::{BackendError, BackendRead, BackendWrite, ObjectMetadata, Result};
 use crate::id::Id;
 use crate::obj::{ObjectKind, DIRECTORY_OBJECTS};
+use crate::process::format::{Format, Formatter};
+
+/// Prefix of files which are still being written.
+///
+/// Objects are written to a temporary file next to their final path and
+/// renamed once complete, so that they are never observable in a partial
+/// state.
+pub(crate) const TEMP_PREFIX: &str = ".tmp-";
 
 #[derive(Debug, Clone)]
 pub struct Local {
 	path: PathBuf,
+	layout: Layout,
 }
 
 impl Local {
+	/// Uses the layout stored in the repository at `path`.
+	///
+	/// Falls back to the default layout if there is none (e.g. the repository
+	/// was created before the layout was stored or does not exist yet).
 	pub fn new<P: Into<PathBuf>>(path: P) -> Self {
-		Self { path: path.into() }
-	}
+		let path = path.into();
+
+		let layout = match read_layout(&path) {
+			Ok(layout) => layout.unwrap_or_default(),
+			Err(err) => {
+				// Reported again by `verify`
+				log::error!("Failed to read the layout: {err}");
+				Layout::default()
+			}
+		};
 
-	fn resolve_path(&self, kind: ObjectKind, id: &Id) -> PathBuf {
-		let hex = id.to_hex();
+		Self { path, layout }
+	}
 
-		match kind {
-			ObjectKind::Config => self.path.join(kind.name()),
-			// TODO: Make configurable (0..2)
-			ObjectKind::Pack => self.path.join(&hex[0..2]).join(hex),
-			_ => self.path.join(kind.name()).join(hex),
+	/// Uses `layout` for a new repository at `path`.
+	pub fn with_layout<P: Into<PathBuf>>(path: P, layout: Layout) -> Self {
+		Self {
+			path: path.into(),
+			layout,
 		}
 	}
 
+	pub const fn layout(&self) -> &Layout {
+		&self.layout
+	}
+
+	fn resolve_path(&self, kind: ObjectKind, id: &Id) -> PathBuf {
+		self.path.join(self.layout.path(kind, id))
+	}
+
 	fn check_path<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
 		let path = self.path.join(path);
 
 		log::debug!("Verifing {}", path.display());
 
-		if !path.try_exists().unwrap() {
-			return Err(());
-		}
+		let meta = path
+			.metadata()
+			.map_err(|err| BackendError::io(path.display(), err))?;
 
-		if path.metadata().unwrap().permissions().readonly() {
-			return Err(());
+		if meta.permissions().readonly() {
+			return Err(BackendError::PermissionDenied(path.display().to_string()));
 		}
 
 		Ok(path)
 	}
 }
 
+pub(crate) fn is_temp(name: &std::ffi::OsStr) -> bool {
+	name.to_string_lossy().starts_with(TEMP_PREFIX)
+}
+
+/// Writes `buf` to a temporary file, syncs it and renames it to `path`.
+pub(crate) fn write_atomic(path: &Path, buf: &[u8]) -> Result<()> {
+	let io = |err| BackendError::io(path.display(), err);
+
+	let dir = path.parent().unwrap_or_else(|| Path::new("."));
+	std::fs::create_dir_all(dir).map_err(io)?;
+
+	let name = path
+		.file_name()
+		.map(|name| name.to_string_lossy())
+		.unwrap_or_default();
+	let temp = dir.join(format!(
+		"{TEMP_PREFIX}{name}-{:016x}",
+		rand::random::<u64>()
+	));
+
+	let write = || -> std::io::Result<()> {
+		let mut w = OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&temp)?;
+
+		w.write_all(buf)?;
+		w.sync_all()?;
+		drop(w);
+
+		std::fs::rename(&temp, path)?;
+
+		sync_dir(dir)
+	};
+
+	write().map_err(|err| {
+		// Might already be renamed, in which case there is nothing to remove
+		let _ = std::fs::remove_file(&temp);
+		io(err)
+	})
+}
+
+/// Persists the entries (e.g. a rename) of the directory `dir`.
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+	#[cfg(target_family = "unix")]
+	{
+		File::open(dir)?.sync_all()
+	}
+
+	#[cfg(not(target_family = "unix"))]
+	{
+		let _ = dir;
+		Ok(())
+	}
+}
+
+fn read_layout(root: &Path) -> Result<Option<Layout>> {
+	let path = root.join(LAYOUT_NAME);
+
+	let bytes = match std::fs::read(&path) {
+		Ok(bytes) => bytes,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+		Err(err) => return Err(BackendError::io(path.display(), err)),
+	};
+
+	match Formatter::Cbor.parse::<Layout>(&bytes) {
+		Ok(layout) if layout.is_valid() => Ok(Some(layout)),
+		_ => Err(BackendError::Corrupt(format!(
+			"`{}` is not a valid layout",
+			path.display()
+		))),
+	}
+}
+
 impl BackendRead for Local {
 	type Iter = Iter;
 
@@ -61,13 +166,28 @@ impl BackendRead for Local {
 		{
 			let path = self.check_path("config")?;
 			if !path.is_file() {
-				return Err(());
+				return Err(BackendError::Corrupt(format!(
+					"`{}` is not a file",
+					path.display()
+				)));
 			}
 		}
 
 		for kind in DIRECTORY_OBJECTS {
-			if !self.check_path(kind.name())?.is_dir() {
-				return Err(());
+			let path = self.check_path(kind.name())?;
+			if !path.is_dir() {
+				return Err(BackendError::Corrupt(format!(
+					"`{}` is not a directory",
+					path.display()
+				)));
+			}
+		}
+
+		if let Some(layout) = read_layout(&self.path)? {
+			if layout != self.layout {
+				return Err(BackendError::Corrupt(
+					"The stored layout differs from the used one".to_string(),
+				));
 			}
 		}
 
@@ -75,29 +195,40 @@ impl BackendRead for Local {
 	}
 
 	fn iter(&self, kind: ObjectKind) -> Result<Self::Iter> {
+		let path = self.path.join(kind.name());
+		let exists = path
+			.try_exists()
+			.map_err(|err| BackendError::io(path.display(), err))?;
+
 		if kind == ObjectKind::Config {
-			Ok(Iter::from_config(
-				std::fs::try_exists(self.path.join(kind.name())).unwrap(),
-			))
+			Ok(Iter::from_config(exists))
+		} else if exists {
+			Ok(Iter::new(path))
 		} else {
-			Ok(Iter::new(self.path.join(kind.name())))
+			// Nothing of this kind was stored yet
+			Ok(Iter::empty())
 		}
 	}
 
 	fn exists(&self, kind: ObjectKind, id: &Id) -> Result<()> {
 		let path = self.resolve_path(kind, id);
 
-		if !path.try_exists().unwrap() {
-			return Err(());
+		if path
+			.try_exists()
+			.map_err(|err| BackendError::io(path.display(), err))?
+		{
+			Ok(())
+		} else {
+			Err(BackendError::NotFound(path.display().to_string()))
 		}
-
-		Ok(())
 	}
 
 	fn meta(&self, kind: ObjectKind, id: &Id) -> Result<ObjectMetadata> {
 		let path = self.resolve_path(kind, id);
 
-		let meta = path.metadata().unwrap();
+		let meta = path
+			.metadata()
+			.map_err(|err| BackendError::io(path.display(), err))?;
 
 		Ok(ObjectMetadata {
 			accessed: meta.accessed().ok().map(|t| t.into()),
@@ -110,11 +241,13 @@ impl BackendRead for Local {
 	fn read_at(&self, kind: ObjectKind, id: &Id, offset: u32, buf: &mut [u8]) -> Result<usize> {
 		let path = self.resolve_path(kind, id);
 
-		let mut r = File::open(path).unwrap();
+		let io = |err| BackendError::io(path.display(), err);
+
+		let mut r = File::open(&path).map_err(io)?;
 
-		r.seek(SeekFrom::Start(offset as u64)).unwrap();
+		r.seek(SeekFrom::Start(u64::from(offset))).map_err(io)?;
 
-		r.read_exact(buf).unwrap();
+		r.read_exact(buf).map_err(io)?;
 
 		Ok(buf.len())
 	}
@@ -122,9 +255,11 @@ impl BackendRead for Local {
 	fn read_all(&self, kind: ObjectKind, id: &Id, buf: &mut Vec<u8>) -> Result<usize> {
 		let path = self.resolve_path(kind, id);
 
-		let mut r = File::open(path).unwrap();
+		let io = |err| BackendError::io(path.display(), err);
+
+		let mut r = File::open(&path).map_err(io)?;
 
-		Ok(r.read_to_end(buf).unwrap())
+		r.read_to_end(buf).map_err(io)
 	}
 }
 
@@ -142,10 +277,21 @@ impl Iter {
 	fn new(path: PathBuf) -> Self {
 		let iter = WalkDir::new(path)
 			.into_iter()
-			.filter_map(walkdir::Result::ok)
-			.filter(|e| e.file_type().is_file())
-			.map(|e| Id::from_str(&e.file_name().to_string_lossy()))
-			.map(|e| if let Ok(e) = e { Ok(e) } else { Err(()) });
+			.filter(|e| !matches!(e, Ok(e) if !e.file_type().is_file() || is_temp(e.file_name())))
+			.map(|e| {
+				let e = e.map_err(|err| {
+					let location = err
+						.path()
+						.map(|p| p.display().to_string())
+						.unwrap_or_default();
+
+					BackendError::io(location, err.into())
+				})?;
+
+				Id::from_str(&e.file_name().to_string_lossy()).map_err(|_| {
+					BackendError::Corrupt(format!("Invalid object name `{}`", e.path().display()))
+				})
+			});
 
 		Self {
 			inner: Box::new(iter),
@@ -153,14 +299,18 @@ impl Iter {
 	}
 
 	fn from_config(exists: bool) -> Self {
-		let iter: Box<dyn Iterator<Item = Result<Id>>> = if exists {
-			Box::new(std::iter::once(Ok(Id::ZERO)))
+		if exists {
+			Self {
+				inner: Box::new(std::iter::once(Ok(Id::ZERO))),
+			}
 		} else {
-			Box::new(std::iter::empty())
-		};
+			Self::empty()
+		}
+	}
 
+	fn empty() -> Self {
 		Self {
-			inner: Box::new(iter),
+			inner: Box::new(std::iter::empty()),
 		}
 	}
 }
@@ -175,48 +325,77 @@ impl Iterator for Iter {
 
 impl BackendWrite for Local {
 	fn create(&mut self) -> Result<()> {
-		std::fs::create_dir_all(&self.path).unwrap();
+		let create_dir = |path: PathBuf| {
+			std::fs::create_dir_all(&path).map_err(|err| BackendError::io(path.display(), err))
+		};
+
+		create_dir(self.path.clone())?;
 
 		{
-			std::fs::File::create(self.path.join(ObjectKind::Config.name())).unwrap();
+			let path = self.path.join(ObjectKind::Config.name());
+			std::fs::File::create(&path).map_err(|err| BackendError::io(path.display(), err))?;
 		}
 
-		for kind in DIRECTORY_OBJECTS {
-			std::fs::create_dir_all(self.path.join(kind.name())).unwrap();
+		{
+			let bytes = Formatter::Cbor
+				.format(&self.layout)
+				.map_err(|err| BackendError::Corrupt(format!("Invalid layout: {err}")))?;
+			write_atomic(&self.path.join(LAYOUT_NAME), &bytes)?;
 		}
 
-		for byte in u8::MIN..=u8::MAX {
-			std::fs::create_dir_all(
-				self.path
-					.join(ObjectKind::Pack.name())
-					.join(format!("{byte:02x}")),
-			)
-			.unwrap();
+		for kind in DIRECTORY_OBJECTS {
+			create_dir(self.path.join(kind.name()))?;
 		}
 
+		// Shard directories are created on demand, as pre-creating them would
+		// result in up to `16^16` directories
 		Ok(())
 	}
 
 	fn remove(&mut self, kind: ObjectKind, id: &Id) -> Result<()> {
 		let path = self.resolve_path(kind, id);
-		std::fs::remove_file(path).unwrap();
-		Ok(())
+
+		std::fs::remove_file(&path).map_err(|err| BackendError::io(path.display(), err))
 	}
 
 	fn write_all(&mut self, kind: ObjectKind, id: &Id, buf: &[u8]) -> Result<()> {
-		let path = self.resolve_path(kind, id);
+		write_atomic(&self.resolve_path(kind, id), buf)
+	}
 
-		let mut w = OpenOptions::new()
-			.create(true)
-			.write(true)
-			.open(path)
-			.unwrap();
+	fn clean(&mut self, age: Duration) -> Result<u64> {
+		let mut removed = 0;
 
-		w.set_len(buf.len().try_into().unwrap()).unwrap();
-		w.write_all(buf).unwrap();
-		w.sync_all().unwrap();
+		for entry in WalkDir::new(&self.path) {
+			let entry = entry.map_err(|err| {
+				let location = err
+					.path()
+					.map(|p| p.display().to_string())
+					.unwrap_or_default();
 
-		Ok(())
+				BackendError::io(location, err.into())
+			})?;
+
+			if !entry.file_type().is_file() || !is_temp(entry.file_name()) {
+				continue;
+			}
+
+			let path = entry.path();
+			let io = |err| BackendError::io(path.display(), err);
+
+			let modified = entry.metadata().map_err(|err| io(err.into()))?.modified();
+			let elapsed = modified
+				.ok()
+				.and_then(|time| SystemTime::now().duration_since(time).ok())
+				.unwrap_or_default();
+
+			if elapsed >= age {
+				log::debug!("Removing leftover {}", path.display());
+				std::fs::remove_file(path).map_err(io)?;
+				removed += 1;
+			}
+		}
+
+		Ok(removed)
 	}
 }
 
@@ -226,8 +405,45 @@ mod test {
 
 	#[test]
 	fn local() {
-		let mut l = Local::new("test_repo");
+		let path = std::env::temp_dir().join(format!("dechst-{}", Id::random()));
+		let mut l = Local::new(&path);
 		l.create().unwrap();
 		l.verify().unwrap();
+
+		std::fs::remove_dir_all(path).unwrap();
+	}
+
+	#[test]
+	fn atomic_writes() {
+		let path = std::env::temp_dir().join(format!("dechst-{}", Id::random()));
+		let mut l = Local::new(&path);
+		l.create().unwrap();
+
+		let id = Id::random();
+		l.write_all(ObjectKind::Pack, &id, b"pack").unwrap();
+		l.write_all(ObjectKind::Pack, &id, b"new").unwrap();
+
+		// Leftover of an interrupted write
+		let dir = l.resolve_path(ObjectKind::Pack, &id);
+		let dir = dir.parent().unwrap();
+		std::fs::write(dir.join(format!("{TEMP_PREFIX}{id}-0")), b"pa").unwrap();
+
+		let ids = l
+			.iter(ObjectKind::Pack)
+			.unwrap()
+			.collect::<Result<Vec<_>>>()
+			.unwrap();
+		assert_eq!(ids, [id]);
+		assert_eq!(std::fs::read_dir(dir).unwrap().count(), 2);
+
+		let mut buf = Vec::new();
+		l.read_all(ObjectKind::Pack, &id, &mut buf).unwrap();
+		assert_eq!(buf, b"new");
+
+		assert_eq!(l.clean(Duration::from_secs(60 * 60)).unwrap(), 0);
+		assert_eq!(l.clean(Duration::ZERO).unwrap(), 1);
+		assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+
+		std::fs::remove_dir_all(path).unwrap();
 	}
 }
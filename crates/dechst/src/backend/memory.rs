@@ -0,0 +1,221 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use chrono::{DateTime, Utc};
+
+use crate::backend::{BackendError, BackendRead, BackendWrite, ObjectMetadata, Result};
+use crate::id::Id;
+use crate::obj::ObjectKind;
+
+pub const MOUNT_POINT: &str = "mem://";
+
+#[derive(Debug, Clone)]
+struct Object {
+	bytes: Vec<u8>,
+	created: DateTime<Utc>,
+	modified: DateTime<Utc>,
+}
+
+/// Keeps all objects in memory.
+///
+/// Clones share the same objects, so a clone can be handed to a repository
+/// while the original is used to inspect the stored objects.
+#[derive(Default, Debug, Clone)]
+pub struct Memory {
+	objects: Arc<RwLock<HashMap<(ObjectKind, Id), Object>>>,
+}
+
+impl Memory {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the number of stored objects of kind `kind`.
+	pub fn len(&self, kind: ObjectKind) -> usize {
+		self.read().keys().filter(|(k, _)| *k == kind).count()
+	}
+
+	fn read(&self) -> RwLockReadGuard<'_, HashMap<(ObjectKind, Id), Object>> {
+		// A panic while holding the lock can not leave the map in an
+		// inconsistent state
+		self.objects.read().unwrap_or_else(|err| err.into_inner())
+	}
+
+	fn write(&self) -> RwLockWriteGuard<'_, HashMap<(ObjectKind, Id), Object>> {
+		self.objects.write().unwrap_or_else(|err| err.into_inner())
+	}
+
+	fn with<T, F: FnOnce(&Object) -> Result<T>>(
+		&self,
+		kind: ObjectKind,
+		id: &Id,
+		f: F,
+	) -> Result<T> {
+		match self.read().get(&(kind, *id)) {
+			Some(object) => f(object),
+			None => Err(BackendError::NotFound(location(kind, id))),
+		}
+	}
+}
+
+fn location(kind: ObjectKind, id: &Id) -> String {
+	match kind {
+		ObjectKind::Config => format!("{MOUNT_POINT}{}", kind.name()),
+		_ => format!("{MOUNT_POINT}{}/{id}", kind.name()),
+	}
+}
+
+impl BackendRead for Memory {
+	type Iter = std::vec::IntoIter<Result<Id>>;
+
+	fn mount_point(&self) -> Cow<'_, str> {
+		Cow::Borrowed(MOUNT_POINT)
+	}
+
+	fn verify(&self) -> Result<()> {
+		self.exists(ObjectKind::Config, &Id::ZERO)
+	}
+
+	fn iter(&self, kind: ObjectKind) -> Result<Self::Iter> {
+		let ids: Vec<_> = self
+			.read()
+			.keys()
+			.filter(|(k, _)| *k == kind)
+			.map(|(_, id)| Ok(*id))
+			.collect();
+
+		Ok(ids.into_iter())
+	}
+
+	fn exists(&self, kind: ObjectKind, id: &Id) -> Result<()> {
+		self.with(kind, id, |_| Ok(()))
+	}
+
+	fn meta(&self, kind: ObjectKind, id: &Id) -> Result<ObjectMetadata> {
+		self.with(kind, id, |object| {
+			Ok(ObjectMetadata {
+				accessed: None,
+				created: Some(object.created),
+				modified: Some(object.modified),
+				len: object.bytes.len() as u64,
+			})
+		})
+	}
+
+	fn read_at(&self, kind: ObjectKind, id: &Id, offset: u32, buf: &mut [u8]) -> Result<usize> {
+		self.with(kind, id, |object| {
+			let start = offset as usize;
+			let bytes = start
+				.checked_add(buf.len())
+				.and_then(|end| object.bytes.get(start..end))
+				.ok_or_else(|| {
+					BackendError::io(location(kind, id), std::io::ErrorKind::UnexpectedEof.into())
+				})?;
+
+			buf.copy_from_slice(bytes);
+
+			Ok(buf.len())
+		})
+	}
+
+	fn read_all(&self, kind: ObjectKind, id: &Id, buf: &mut Vec<u8>) -> Result<usize> {
+		self.with(kind, id, |object| {
+			buf.extend_from_slice(&object.bytes);
+
+			Ok(object.bytes.len())
+		})
+	}
+}
+
+impl BackendWrite for Memory {
+	fn create(&mut self) -> Result<()> {
+		let now = Utc::now();
+
+		self.write()
+			.entry((ObjectKind::Config, Id::ZERO))
+			.or_insert_with(|| Object {
+				bytes: Vec::new(),
+				created: now,
+				modified: now,
+			});
+
+		Ok(())
+	}
+
+	fn remove(&mut self, kind: ObjectKind, id: &Id) -> Result<()> {
+		match self.write().remove(&(kind, *id)) {
+			Some(_) => Ok(()),
+			None => Err(BackendError::NotFound(location(kind, id))),
+		}
+	}
+
+	fn write_all(&mut self, kind: ObjectKind, id: &Id, buf: &[u8]) -> Result<()> {
+		let now = Utc::now();
+
+		self.write()
+			.entry((kind, *id))
+			.and_modify(|object| {
+				object.bytes = buf.to_vec();
+				object.modified = now;
+			})
+			.or_insert_with(|| Object {
+				bytes: buf.to_vec(),
+				created: now,
+				modified: now,
+			});
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::backend::ext::ReadToEnd;
+
+	#[test]
+	fn memory() {
+		let mut backend = Memory::new();
+		assert!(backend.verify().unwrap_err().is_not_found());
+
+		backend.create().unwrap();
+		backend.verify().unwrap();
+
+		let id = Id::random();
+		let shared = backend.clone();
+
+		backend
+			.write_all(ObjectKind::Pack, &id, b"0123456789")
+			.unwrap();
+		assert_eq!(shared.len(ObjectKind::Pack), 1);
+		assert_eq!(shared.meta(ObjectKind::Pack, &id).unwrap().len, 10);
+		assert_eq!(
+			shared.read_to_end(ObjectKind::Pack, &id).unwrap(),
+			b"0123456789"
+		);
+
+		let mut buf = [0u8; 4];
+		assert_eq!(
+			shared.read_at(ObjectKind::Pack, &id, 6, &mut buf).unwrap(),
+			4
+		);
+		assert_eq!(&buf, b"6789");
+		assert!(shared.read_at(ObjectKind::Pack, &id, 7, &mut buf).is_err());
+
+		let ids: Vec<_> = shared
+			.iter(ObjectKind::Pack)
+			.unwrap()
+			.collect::<Result<_>>()
+			.unwrap();
+		assert_eq!(ids, [id]);
+		assert_eq!(shared.iter(ObjectKind::Index).unwrap().count(), 0);
+
+		backend.remove(ObjectKind::Pack, &id).unwrap();
+		assert!(shared.exists(ObjectKind::Pack, &id).is_err());
+		assert!(backend
+			.remove(ObjectKind::Pack, &id)
+			.unwrap_err()
+			.is_not_found());
+	}
+}
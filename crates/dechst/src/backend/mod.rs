@@ -1,14 +1,86 @@
+pub mod cache;
+pub mod command;
 pub mod ext;
+pub mod layout;
 pub mod local;
+pub mod memory;
+#[cfg(feature = "backend-rest")]
+pub mod rest;
+#[cfg(feature = "backend-s3")]
+pub mod s3;
+pub mod sftp;
 pub mod typed;
 
 use std::borrow::Cow;
 use std::fmt;
+use std::time::Duration;
 
 use crate::id::Id;
 use crate::obj::{ObjectKind, ObjectMetadata};
 
-pub type Result<T, E = ()> = std::result::Result<T, E>;
+#[derive(Debug)]
+pub enum BackendError {
+	/// The object or location does not exist
+	NotFound(String),
+	PermissionDenied(String),
+	AlreadyExists(String),
+	/// Any other I/O error together with the location it occurred at
+	Io {
+		location: String,
+		source: std::io::Error,
+	},
+	/// The backend contains invalid data (e.g. an object with an invalid
+	/// name)
+	Corrupt(String),
+}
+
+impl BackendError {
+	/// Maps `err` to the matching variant and attaches the `location` (e.g.
+	/// path or url) it occurred at.
+	pub fn io<L: fmt::Display>(location: L, err: std::io::Error) -> Self {
+		let location = location.to_string();
+
+		match err.kind() {
+			std::io::ErrorKind::NotFound => Self::NotFound(location),
+			std::io::ErrorKind::PermissionDenied => Self::PermissionDenied(location),
+			std::io::ErrorKind::AlreadyExists => Self::AlreadyExists(location),
+			_ => Self::Io {
+				location,
+				source: err,
+			},
+		}
+	}
+
+	pub const fn is_not_found(&self) -> bool {
+		matches!(self, Self::NotFound(_))
+	}
+}
+
+impl fmt::Display for BackendError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::NotFound(location) => write!(f, "`{location}` does not exist"),
+			Self::PermissionDenied(location) => write!(f, "Permission denied for `{location}`"),
+			Self::AlreadyExists(location) => write!(f, "`{location}` already exists"),
+			Self::Io { location, source } => write!(f, "I/O at `{location}`: {source}"),
+			Self::Corrupt(reason) => write!(f, "Corrupt backend: {reason}"),
+		}
+	}
+}
+
+impl std::error::Error for BackendError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io { source, .. } => Some(source),
+			Self::NotFound(_)
+			| Self::PermissionDenied(_)
+			| Self::AlreadyExists(_)
+			| Self::Corrupt(_) => None,
+		}
+	}
+}
+
+pub type Result<T, E = BackendError> = std::result::Result<T, E>;
 
 pub trait BackendRead: fmt::Debug + Clone + Send + Sync + 'static {
 	type Iter: Iterator<Item = Result<Id>>;
@@ -34,5 +106,16 @@ pub trait BackendWrite: BackendRead {
 
 	fn remove(&mut self, kind: ObjectKind, id: &Id) -> Result<()>;
 
+	/// Writes the object at once; A partially written object must never be
+	/// observable.
 	fn write_all(&mut self, kind: ObjectKind, id: &Id, buf: &[u8]) -> Result<()>;
+
+	/// Removes leftovers of interrupted writes (e.g. temporary files) which
+	/// are at least `age` old.
+	///
+	/// Returns the number of removed leftovers.
+	fn clean(&mut self, age: Duration) -> Result<u64> {
+		let _ = age;
+		Ok(0)
+	}
 }
@@ -0,0 +1,1195 @@
+//! Repositories on SSH servers, accessed with the `sftp` subsystem of the
+//! system's `ssh` (so that its configuration, keys and known hosts are used).
+//!
+//! Repositories use the same structure as [`Local`](super::local::Local)
+//! ones, which allows accessing them directly on the server.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::backend::layout::{Layout, LAYOUT_NAME};
+use crate::backend::local::TEMP_PREFIX;
+use crate::backend::{BackendError, BackendRead, BackendWrite, ObjectMetadata, Result};
+use crate::id::Id;
+use crate::obj::{ObjectKind, DIRECTORY_OBJECTS};
+use crate::process::format::{Format, Formatter};
+
+/// Prefix of repository locations on SSH servers (e.g.
+/// `sftp://user@host:22/repo`).
+///
+/// The path is relative to the home directory of the user, unless it starts
+/// with another `/` (e.g. `sftp://host//srv/repo`).
+pub const URL_PREFIX: &str = "sftp://";
+
+const SSH_FXP_INIT: u8 = 1;
+const SSH_FXP_VERSION: u8 = 2;
+const SSH_FXP_OPEN: u8 = 3;
+const SSH_FXP_CLOSE: u8 = 4;
+const SSH_FXP_READ: u8 = 5;
+const SSH_FXP_WRITE: u8 = 6;
+const SSH_FXP_OPENDIR: u8 = 11;
+const SSH_FXP_READDIR: u8 = 12;
+const SSH_FXP_REMOVE: u8 = 13;
+const SSH_FXP_MKDIR: u8 = 14;
+const SSH_FXP_STAT: u8 = 17;
+const SSH_FXP_RENAME: u8 = 18;
+const SSH_FXP_STATUS: u8 = 101;
+const SSH_FXP_HANDLE: u8 = 102;
+const SSH_FXP_DATA: u8 = 103;
+const SSH_FXP_NAME: u8 = 104;
+const SSH_FXP_ATTRS: u8 = 105;
+const SSH_FXP_EXTENDED: u8 = 200;
+
+const SSH_FX_OK: u32 = 0;
+const SSH_FX_EOF: u32 = 1;
+const SSH_FX_NO_SUCH_FILE: u32 = 2;
+const SSH_FX_PERMISSION_DENIED: u32 = 3;
+const SSH_FX_OP_UNSUPPORTED: u32 = 8;
+
+const SSH_FXF_READ: u32 = 0x01;
+const SSH_FXF_WRITE: u32 = 0x02;
+const SSH_FXF_CREAT: u32 = 0x08;
+const SSH_FXF_TRUNC: u32 = 0x10;
+const SSH_FXF_EXCL: u32 = 0x20;
+
+const SSH_FILEXFER_ATTR_SIZE: u32 = 0x01;
+const SSH_FILEXFER_ATTR_UIDGID: u32 = 0x02;
+const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x04;
+const SSH_FILEXFER_ATTR_ACMODTIME: u32 = 0x08;
+const SSH_FILEXFER_ATTR_EXTENDED: u32 = 0x8000_0000;
+
+const POSIX_RENAME: &str = "posix-rename@openssh.com";
+const FSYNC: &str = "fsync@openssh.com";
+
+/// Largest chunk read or written with a single request; Servers are only
+/// required to support packets of up to 34000 bytes.
+const CHUNK_LEN: u32 = 32 * 1024;
+const MAX_PACKET_LEN: usize = 256 * 1024;
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFREG: u32 = 0o100_000;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Attrs {
+	size: Option<u64>,
+	permissions: Option<u32>,
+	atime: Option<u32>,
+	mtime: Option<u32>,
+}
+
+impl Attrs {
+	fn is_dir(&self) -> bool {
+		self.permissions
+			.is_some_and(|permissions| permissions & S_IFMT == S_IFDIR)
+	}
+
+	fn is_file(&self) -> bool {
+		self.permissions
+			.is_some_and(|permissions| permissions & S_IFMT == S_IFREG)
+	}
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Reads the fields of a packet.
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+	fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+		if self.0.len() < len {
+			return Err(invalid_data("Packet too short"));
+		}
+
+		let (bytes, rest) = self.0.split_at(len);
+		self.0 = rest;
+
+		Ok(bytes)
+	}
+
+	fn u32(&mut self) -> io::Result<u32> {
+		let bytes = self.bytes(4)?;
+		Ok(u32::from_be_bytes(bytes.try_into().expect("4 bytes")))
+	}
+
+	fn u64(&mut self) -> io::Result<u64> {
+		let bytes = self.bytes(8)?;
+		Ok(u64::from_be_bytes(bytes.try_into().expect("8 bytes")))
+	}
+
+	fn string(&mut self) -> io::Result<&'a [u8]> {
+		let len = self.u32()?;
+		self.bytes(len as usize)
+	}
+
+	fn attrs(&mut self) -> io::Result<Attrs> {
+		let flags = self.u32()?;
+		let mut attrs = Attrs::default();
+
+		if flags & SSH_FILEXFER_ATTR_SIZE != 0 {
+			attrs.size = Some(self.u64()?);
+		}
+		if flags & SSH_FILEXFER_ATTR_UIDGID != 0 {
+			self.bytes(8)?;
+		}
+		if flags & SSH_FILEXFER_ATTR_PERMISSIONS != 0 {
+			attrs.permissions = Some(self.u32()?);
+		}
+		if flags & SSH_FILEXFER_ATTR_ACMODTIME != 0 {
+			attrs.atime = Some(self.u32()?);
+			attrs.mtime = Some(self.u32()?);
+		}
+		if flags & SSH_FILEXFER_ATTR_EXTENDED != 0 {
+			for _ in 0..self.u32()? {
+				self.string()?;
+				self.string()?;
+			}
+		}
+
+		Ok(attrs)
+	}
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+	buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+	buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_string<S: AsRef<[u8]>>(buf: &mut Vec<u8>, s: S) {
+	let s = s.as_ref();
+	put_u32(buf, s.len() as u32);
+	buf.extend_from_slice(s);
+}
+
+/// Converts a status response into a result.
+fn status(body: &[u8]) -> io::Result<()> {
+	let mut d = Decoder(body);
+	let code = d.u32()?;
+	let message = String::from_utf8_lossy(d.string().unwrap_or_default());
+
+	let kind = match code {
+		SSH_FX_OK => return Ok(()),
+		SSH_FX_EOF => io::ErrorKind::UnexpectedEof,
+		SSH_FX_NO_SUCH_FILE => io::ErrorKind::NotFound,
+		SSH_FX_PERMISSION_DENIED => io::ErrorKind::PermissionDenied,
+		SSH_FX_OP_UNSUPPORTED => io::ErrorKind::Unsupported,
+		_ => io::ErrorKind::Other,
+	};
+
+	Err(io::Error::new(kind, format!("{message} ({code})")))
+}
+
+/// Client side of a SFTP (version 3) channel.
+struct Session {
+	reader: BufReader<Box<dyn Read + Send>>,
+	writer: Box<dyn Write + Send>,
+	/// Process providing the channel (e.g. `ssh`)
+	child: Option<Child>,
+	next_id: u32,
+	extensions: Vec<String>,
+}
+
+impl fmt::Debug for Session {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Session")
+			.field("child", &self.child)
+			.field("extensions", &self.extensions)
+			.finish_non_exhaustive()
+	}
+}
+
+impl Session {
+	fn new(
+		reader: Box<dyn Read + Send>,
+		writer: Box<dyn Write + Send>,
+		child: Option<Child>,
+	) -> io::Result<Self> {
+		let mut session = Self {
+			reader: BufReader::new(reader),
+			writer,
+			child,
+			next_id: 0,
+			extensions: Vec::new(),
+		};
+
+		let mut init = Vec::new();
+		put_u32(&mut init, 3);
+		session.write_packet(SSH_FXP_INIT, &init)?;
+
+		let (kind, body) = session.read_packet().map_err(|err| {
+			if err.kind() == io::ErrorKind::UnexpectedEof {
+				io::Error::new(io::ErrorKind::ConnectionAborted, "Connection closed")
+			} else {
+				err
+			}
+		})?;
+		if kind != SSH_FXP_VERSION {
+			return Err(invalid_data(format!("Expected version, got {kind}")));
+		}
+
+		let mut d = Decoder(&body);
+		let version = d.u32()?;
+		if version < 3 {
+			return Err(io::Error::new(
+				io::ErrorKind::Unsupported,
+				format!("Unsupported SFTP version {version}"),
+			));
+		}
+
+		while !d.0.is_empty() {
+			let name = d.string()?;
+			d.string()?;
+			session
+				.extensions
+				.push(String::from_utf8_lossy(name).into_owned());
+		}
+
+		Ok(session)
+	}
+
+	fn supports(&self, extension: &str) -> bool {
+		self.extensions.iter().any(|e| e == extension)
+	}
+
+	fn write_packet(&mut self, kind: u8, payload: &[u8]) -> io::Result<()> {
+		let len = u32::try_from(payload.len() + 1).map_err(invalid_data)?;
+
+		self.writer.write_all(&len.to_be_bytes())?;
+		self.writer.write_all(&[kind])?;
+		self.writer.write_all(payload)?;
+		self.writer.flush()
+	}
+
+	fn read_packet(&mut self) -> io::Result<(u8, Vec<u8>)> {
+		let mut len = [0u8; 4];
+		self.reader.read_exact(&mut len)?;
+
+		let len = u32::from_be_bytes(len) as usize;
+		if len == 0 || len > MAX_PACKET_LEN {
+			return Err(invalid_data(format!("Invalid packet length {len}")));
+		}
+
+		let mut body = vec![0u8; len];
+		self.reader.read_exact(&mut body)?;
+		let kind = body.remove(0);
+
+		Ok((kind, body))
+	}
+
+	/// Sends a request and returns the kind and body (after the id) of its
+	/// response.
+	fn request(&mut self, kind: u8, payload: &[u8]) -> io::Result<(u8, Vec<u8>)> {
+		let id = self.next_id;
+		self.next_id = id.wrapping_add(1);
+
+		let mut packet = Vec::with_capacity(payload.len() + 4);
+		put_u32(&mut packet, id);
+		packet.extend_from_slice(payload);
+		self.write_packet(kind, &packet)?;
+
+		let (kind, mut body) = self.read_packet()?;
+		if Decoder(&body).u32()? != id {
+			return Err(invalid_data("Unexpected response id"));
+		}
+		body.drain(..4);
+
+		Ok((kind, body))
+	}
+
+	fn expect_status(&mut self, kind: u8, payload: &[u8]) -> io::Result<()> {
+		match self.request(kind, payload)? {
+			(SSH_FXP_STATUS, body) => status(&body),
+			(kind, _) => Err(invalid_data(format!("Expected status, got {kind}"))),
+		}
+	}
+
+	/// Fails with the status if the response is not of kind `expected`.
+	fn expect(&mut self, expected: u8, kind: u8, payload: &[u8]) -> io::Result<Vec<u8>> {
+		match self.request(kind, payload)? {
+			(kind, body) if kind == expected => Ok(body),
+			(SSH_FXP_STATUS, body) => {
+				status(&body)?;
+				Err(invalid_data("Unexpected success"))
+			}
+			(kind, _) => Err(invalid_data(format!("Expected {expected}, got {kind}"))),
+		}
+	}
+
+	fn open(&mut self, path: &str, flags: u32) -> io::Result<Vec<u8>> {
+		let mut payload = Vec::new();
+		put_string(&mut payload, path);
+		put_u32(&mut payload, flags);
+		put_u32(&mut payload, 0);
+
+		let body = self.expect(SSH_FXP_HANDLE, SSH_FXP_OPEN, &payload)?;
+		Ok(Decoder(&body).string()?.to_vec())
+	}
+
+	fn close(&mut self, handle: &[u8]) -> io::Result<()> {
+		let mut payload = Vec::new();
+		put_string(&mut payload, handle);
+
+		self.expect_status(SSH_FXP_CLOSE, &payload)
+	}
+
+	/// Returns `None` at the end of the file.
+	fn read(&mut self, handle: &[u8], offset: u64, len: u32) -> io::Result<Option<Vec<u8>>> {
+		let mut payload = Vec::new();
+		put_string(&mut payload, handle);
+		put_u64(&mut payload, offset);
+		put_u32(&mut payload, len);
+
+		match self.expect(SSH_FXP_DATA, SSH_FXP_READ, &payload) {
+			Ok(body) => Ok(Some(Decoder(&body).string()?.to_vec())),
+			Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+			Err(err) => Err(err),
+		}
+	}
+
+	fn write(&mut self, handle: &[u8], offset: u64, data: &[u8]) -> io::Result<()> {
+		let mut payload = Vec::with_capacity(data.len() + handle.len() + 16);
+		put_string(&mut payload, handle);
+		put_u64(&mut payload, offset);
+		put_string(&mut payload, data);
+
+		self.expect_status(SSH_FXP_WRITE, &payload)
+	}
+
+	/// Flushes the file to disk if the server supports it.
+	fn fsync(&mut self, handle: &[u8]) -> io::Result<()> {
+		if !self.supports(FSYNC) {
+			return Ok(());
+		}
+
+		let mut payload = Vec::new();
+		put_string(&mut payload, FSYNC);
+		put_string(&mut payload, handle);
+
+		self.expect_status(SSH_FXP_EXTENDED, &payload)
+	}
+
+	fn stat(&mut self, path: &str) -> io::Result<Attrs> {
+		let mut payload = Vec::new();
+		put_string(&mut payload, path);
+
+		let body = self.expect(SSH_FXP_ATTRS, SSH_FXP_STAT, &payload)?;
+		Decoder(&body).attrs()
+	}
+
+	fn read_dir(&mut self, path: &str) -> io::Result<Vec<(String, Attrs)>> {
+		let mut payload = Vec::new();
+		put_string(&mut payload, path);
+
+		let body = self.expect(SSH_FXP_HANDLE, SSH_FXP_OPENDIR, &payload)?;
+		let handle = Decoder(&body).string()?.to_vec();
+
+		let mut payload = Vec::new();
+		put_string(&mut payload, &handle);
+
+		let mut entries = Vec::new();
+		let result = loop {
+			let body = match self.expect(SSH_FXP_NAME, SSH_FXP_READDIR, &payload) {
+				Ok(body) => body,
+				Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+				Err(err) => break Err(err),
+			};
+
+			let mut d = Decoder(&body);
+			let parsed = (|| {
+				for _ in 0..d.u32()? {
+					let name = String::from_utf8_lossy(d.string()?).into_owned();
+					d.string()?;
+					let attrs = d.attrs()?;
+
+					if name != "." && name != ".." {
+						entries.push((name, attrs));
+					}
+				}
+
+				Ok(())
+			})();
+
+			if let Err(err) = parsed {
+				break Err(err);
+			}
+		};
+
+		self.close(&handle)?;
+		result.map(|_| entries)
+	}
+
+	fn remove(&mut self, path: &str) -> io::Result<()> {
+		let mut payload = Vec::new();
+		put_string(&mut payload, path);
+
+		self.expect_status(SSH_FXP_REMOVE, &payload)
+	}
+
+	fn mkdir(&mut self, path: &str) -> io::Result<()> {
+		let mut payload = Vec::new();
+		put_string(&mut payload, path);
+		put_u32(&mut payload, 0);
+
+		self.expect_status(SSH_FXP_MKDIR, &payload)
+	}
+
+	/// Creates `path` and all its missing parents.
+	fn mkdir_all(&mut self, path: &str) -> io::Result<()> {
+		let mut current = String::new();
+
+		for (i, component) in path.split('/').enumerate() {
+			if i > 0 {
+				current.push('/');
+			}
+			current.push_str(component);
+
+			if component.is_empty() || component == "." {
+				continue;
+			}
+
+			if let Err(err) = self.mkdir(&current) {
+				// Servers do not agree on the status for existing directories
+				if !self.stat(&current).is_ok_and(|attrs| attrs.is_dir()) {
+					return Err(err);
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Replaces `to` if the server supports it.
+	fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+		let mut payload = Vec::new();
+
+		if self.supports(POSIX_RENAME) {
+			put_string(&mut payload, POSIX_RENAME);
+			put_string(&mut payload, from);
+			put_string(&mut payload, to);
+
+			self.expect_status(SSH_FXP_EXTENDED, &payload)
+		} else {
+			put_string(&mut payload, from);
+			put_string(&mut payload, to);
+
+			self.expect_status(SSH_FXP_RENAME, &payload)
+		}
+	}
+
+	/// Reads the file at `path` (starting at `offset`) until `buf` is full
+	/// or the file ends.
+	///
+	/// Returns the number of bytes read.
+	fn read_into(
+		&mut self,
+		path: &str,
+		offset: u64,
+		buf: &mut Vec<u8>,
+		limit: Option<usize>,
+	) -> io::Result<usize> {
+		let handle = self.open(path, SSH_FXF_READ)?;
+
+		let mut read = 0;
+		let result = loop {
+			let len = limit.map_or(CHUNK_LEN, |limit| {
+				CHUNK_LEN.min(u32::try_from(limit - read).unwrap_or(CHUNK_LEN))
+			});
+			if len == 0 {
+				break Ok(read);
+			}
+
+			match self.read(&handle, offset + read as u64, len) {
+				Ok(Some(data)) if !data.is_empty() => {
+					read += data.len();
+					buf.extend_from_slice(&data);
+				}
+				Ok(_) => break Ok(read),
+				Err(err) => break Err(err),
+			}
+		};
+
+		self.close(&handle)?;
+		result
+	}
+}
+
+impl Drop for Session {
+	fn drop(&mut self) {
+		if let Some(child) = &mut self.child {
+			// Closing the channel ends the session
+			self.writer = Box::new(io::sink());
+
+			if let Err(err) = child.wait() {
+				log::warn!("Failed to wait for the ssh process: {err}");
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Sftp {
+	/// Url for messages
+	location: String,
+	/// Path of the repository on the server
+	root: String,
+	layout: Layout,
+	session: Arc<Mutex<Session>>,
+}
+
+impl Sftp {
+	/// Connects to the server with `ssh` and uses the layout stored in the
+	/// repository (see [`Local::new`](super::local::Local::new)).
+	pub fn connect(url: &str) -> Result<Self> {
+		let location = url.to_string();
+		let io = |err| BackendError::io(&location, err);
+
+		let rest = url.strip_prefix(URL_PREFIX).ok_or_else(|| {
+			io(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("Expected `{URL_PREFIX}`"),
+			))
+		})?;
+
+		let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+		let (user, host) = match authority.rsplit_once('@') {
+			Some((user, host)) => (Some(user), host),
+			None => (None, authority),
+		};
+		let (host, port) = match host.rsplit_once(':') {
+			Some((host, port)) if !port.contains(']') => (host, Some(port)),
+			_ => (host, None),
+		};
+		let host = host.trim_start_matches('[').trim_end_matches(']');
+
+		// Would otherwise be taken as options by `ssh`
+		if host.starts_with('-') || user.is_some_and(|user| user.starts_with('-')) {
+			return Err(io(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Host and user must not start with `-`",
+			)));
+		}
+
+		let mut command = Command::new("ssh");
+		if let Some(port) = port {
+			command.args(["-p", port]);
+		}
+		if let Some(user) = user {
+			command.args(["-l", user]);
+		}
+		command.args(["-s", "--", host, "sftp"]);
+
+		log::debug!("Running {command:?}");
+
+		let mut child = command
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.spawn()
+			.map_err(io)?;
+
+		let stdin = child.stdin.take().expect("Piped stdin");
+		let stdout = child.stdout.take().expect("Piped stdout");
+
+		let session = Session::new(Box::new(stdout), Box::new(stdin), Some(child)).map_err(io)?;
+		let root = if path.is_empty() { "." } else { path };
+
+		Self::with_session(location, root, session)
+	}
+
+	fn with_session(location: String, root: &str, session: Session) -> Result<Self> {
+		let mut sftp = Self {
+			location,
+			root: root.trim_end_matches('/').to_string(),
+			layout: Layout::default(),
+			session: Arc::new(Mutex::new(session)),
+		};
+
+		match sftp.read_layout() {
+			Ok(layout) => sftp.layout = layout.unwrap_or_default(),
+			// Reported again by `verify`
+			Err(err) => log::error!("Failed to read the layout: {err}"),
+		}
+
+		Ok(sftp)
+	}
+
+	/// Uses `layout` for a new repository.
+	pub fn with_layout(mut self, layout: Layout) -> Self {
+		self.layout = layout;
+		self
+	}
+
+	pub const fn layout(&self) -> &Layout {
+		&self.layout
+	}
+
+	fn session(&self) -> MutexGuard<'_, Session> {
+		self.session.lock().unwrap_or_else(|err| err.into_inner())
+	}
+
+	fn path(&self, relative: &str) -> String {
+		format!("{}/{relative}", self.root)
+	}
+
+	fn object_path(&self, kind: ObjectKind, id: &Id) -> String {
+		let relative = self.layout.path(kind, id);
+		let relative: Vec<_> = relative
+			.iter()
+			.map(|component| component.to_string_lossy())
+			.collect();
+
+		self.path(&relative.join("/"))
+	}
+
+	fn io<'a>(&'a self, path: &'a str) -> impl Fn(io::Error) -> BackendError + 'a {
+		move |err| BackendError::io(format!("{}:{path}", self.location), err)
+	}
+
+	fn read_layout(&self) -> Result<Option<Layout>> {
+		let path = self.path(LAYOUT_NAME);
+		let mut bytes = Vec::new();
+
+		match self.session().read_into(&path, 0, &mut bytes, None) {
+			Ok(_) => {}
+			Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(self.io(&path)(err)),
+		}
+
+		match Formatter::Cbor.parse::<Layout>(&bytes) {
+			Ok(layout) if layout.is_valid() => Ok(Some(layout)),
+			_ => Err(BackendError::Corrupt(format!(
+				"`{path}` is not a valid layout"
+			))),
+		}
+	}
+
+	/// Writes `buf` to a temporary file and renames it to `path`.
+	fn write_atomic(&self, path: &str, buf: &[u8]) -> Result<()> {
+		let io = self.io(path);
+		let (dir, name) = path.rsplit_once('/').unwrap_or((".", path));
+		let temp = format!("{dir}/{TEMP_PREFIX}{name}-{:016x}", rand::random::<u64>());
+		let flags = SSH_FXF_WRITE | SSH_FXF_CREAT | SSH_FXF_EXCL;
+
+		let mut session = self.session();
+
+		let handle = match session.open(&temp, flags) {
+			Err(err) if err.kind() == io::ErrorKind::NotFound => {
+				// Shard directories are created on demand
+				session.mkdir_all(dir).map_err(&io)?;
+				session.open(&temp, flags)
+			}
+			result => result,
+		}
+		.map_err(&io)?;
+
+		let mut write = || -> io::Result<()> {
+			let written = (|| {
+				let mut offset = 0;
+				for chunk in buf.chunks(CHUNK_LEN as usize) {
+					session.write(&handle, offset, chunk)?;
+					offset += chunk.len() as u64;
+				}
+
+				session.fsync(&handle)
+			})();
+
+			let closed = session.close(&handle);
+			written?;
+			closed?;
+
+			session.rename(&temp, path)
+		};
+
+		write().map_err(|err| {
+			// Might already be renamed, in which case there is nothing to remove
+			let _ = session.remove(&temp);
+			io(err)
+		})
+	}
+
+	/// Calls `f` for all files below `dir` (recursively).
+	fn walk<F: FnMut(&str, &str, &Attrs) -> Result<()>>(&self, dir: &str, f: &mut F) -> Result<()> {
+		let entries = self.session().read_dir(dir).map_err(self.io(dir))?;
+
+		for (name, attrs) in entries {
+			let path = format!("{dir}/{name}");
+
+			if attrs.is_dir() {
+				self.walk(&path, f)?;
+			} else if attrs.is_file() {
+				f(&path, &name, &attrs)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl BackendRead for Sftp {
+	type Iter = std::vec::IntoIter<Result<Id>>;
+
+	fn mount_point(&self) -> Cow<'_, str> {
+		Cow::Borrowed(&self.location)
+	}
+
+	fn verify(&self) -> Result<()> {
+		let mut session = self.session();
+
+		let path = self.path(ObjectKind::Config.name());
+		if !session.stat(&path).map_err(self.io(&path))?.is_file() {
+			return Err(BackendError::Corrupt(format!("`{path}` is not a file")));
+		}
+
+		for kind in DIRECTORY_OBJECTS {
+			let path = self.path(kind.name());
+			if !session.stat(&path).map_err(self.io(&path))?.is_dir() {
+				return Err(BackendError::Corrupt(format!(
+					"`{path}` is not a directory"
+				)));
+			}
+		}
+
+		drop(session);
+
+		if let Some(layout) = self.read_layout()? {
+			if layout != self.layout {
+				return Err(BackendError::Corrupt(
+					"The stored layout differs from the used one".to_string(),
+				));
+			}
+		}
+
+		Ok(())
+	}
+
+	fn iter(&self, kind: ObjectKind) -> Result<Self::Iter> {
+		if kind == ObjectKind::Config {
+			return match self.exists(kind, &Id::ZERO) {
+				Ok(()) => Ok(vec![Ok(Id::ZERO)].into_iter()),
+				Err(err) if err.is_not_found() => Ok(Vec::new().into_iter()),
+				Err(err) => Err(err),
+			};
+		}
+
+		let mut ids = Vec::new();
+		let walked =
+			self.walk(&self.path(kind.name()), &mut |path, name, _| {
+				if !name.starts_with(TEMP_PREFIX) {
+					ids.push(name.parse().map_err(|_| {
+						BackendError::Corrupt(format!("Invalid object name `{path}`"))
+					}));
+				}
+
+				Ok(())
+			});
+
+		match walked {
+			Ok(()) => Ok(ids.into_iter()),
+			// Nothing of this kind was stored yet
+			Err(err) if err.is_not_found() => Ok(Vec::new().into_iter()),
+			Err(err) => Err(err),
+		}
+	}
+
+	fn exists(&self, kind: ObjectKind, id: &Id) -> Result<()> {
+		self.meta(kind, id).map(|_| ())
+	}
+
+	fn meta(&self, kind: ObjectKind, id: &Id) -> Result<ObjectMetadata> {
+		let path = self.object_path(kind, id);
+		let attrs = self.session().stat(&path).map_err(self.io(&path))?;
+
+		let time = |secs: Option<u32>| -> Option<DateTime<Utc>> {
+			Utc.timestamp_opt(i64::from(secs?), 0).single()
+		};
+
+		Ok(ObjectMetadata {
+			accessed: time(attrs.atime),
+			created: None,
+			modified: time(attrs.mtime),
+			len: attrs.size.unwrap_or_default(),
+		})
+	}
+
+	fn read_at(&self, kind: ObjectKind, id: &Id, offset: u32, buf: &mut [u8]) -> Result<usize> {
+		let path = self.object_path(kind, id);
+		let io = self.io(&path);
+
+		let mut bytes = Vec::with_capacity(buf.len());
+		let read = self
+			.session()
+			.read_into(&path, u64::from(offset), &mut bytes, Some(buf.len()))
+			.map_err(&io)?;
+
+		if read != buf.len() {
+			return Err(io(io::ErrorKind::UnexpectedEof.into()));
+		}
+
+		buf.copy_from_slice(&bytes);
+
+		Ok(read)
+	}
+
+	fn read_all(&self, kind: ObjectKind, id: &Id, buf: &mut Vec<u8>) -> Result<usize> {
+		let path = self.object_path(kind, id);
+
+		self.session()
+			.read_into(&path, 0, buf, None)
+			.map_err(self.io(&path))
+	}
+}
+
+impl BackendWrite for Sftp {
+	fn create(&mut self) -> Result<()> {
+		{
+			let mut session = self.session();
+			session.mkdir_all(&self.root).map_err(self.io(&self.root))?;
+
+			let path = self.path(ObjectKind::Config.name());
+			let io = self.io(&path);
+			let handle = session
+				.open(&path, SSH_FXF_WRITE | SSH_FXF_CREAT | SSH_FXF_TRUNC)
+				.map_err(&io)?;
+			session.close(&handle).map_err(&io)?;
+		}
+
+		{
+			let bytes = Formatter::Cbor
+				.format(&self.layout)
+				.map_err(|err| BackendError::Corrupt(format!("Invalid layout: {err}")))?;
+			self.write_atomic(&self.path(LAYOUT_NAME), &bytes)?;
+		}
+
+		let mut session = self.session();
+		for kind in DIRECTORY_OBJECTS {
+			let path = self.path(kind.name());
+			session.mkdir_all(&path).map_err(self.io(&path))?;
+		}
+
+		Ok(())
+	}
+
+	fn remove(&mut self, kind: ObjectKind, id: &Id) -> Result<()> {
+		let path = self.object_path(kind, id);
+
+		self.session().remove(&path).map_err(self.io(&path))
+	}
+
+	fn write_all(&mut self, kind: ObjectKind, id: &Id, buf: &[u8]) -> Result<()> {
+		self.write_atomic(&self.object_path(kind, id), buf)
+	}
+
+	fn clean(&mut self, age: Duration) -> Result<u64> {
+		let now = SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.unwrap_or_default();
+
+		let mut leftovers = Vec::new();
+		self.walk(&self.root, &mut |path, name, attrs| {
+			let modified = Duration::from_secs(attrs.mtime.map_or(0, u64::from));
+
+			if name.starts_with(TEMP_PREFIX) && now.saturating_sub(modified) >= age {
+				leftovers.push(path.to_string());
+			}
+
+			Ok(())
+		})?;
+
+		let mut session = self.session();
+		for path in &leftovers {
+			log::debug!("Removing leftover {path}");
+			session.remove(path).map_err(self.io(path))?;
+		}
+
+		Ok(leftovers.len() as u64)
+	}
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod test {
+	use std::collections::HashMap;
+	use std::fs::{File, OpenOptions};
+	use std::io::{Seek, SeekFrom};
+	use std::os::unix::fs::MetadataExt;
+	use std::os::unix::net::UnixStream;
+	use std::path::{Path, PathBuf};
+
+	use super::*;
+	use crate::backend::ext::ReadToEnd;
+	use crate::backend::local::Local;
+
+	fn attrs(meta: &std::fs::Metadata) -> Vec<u8> {
+		let mut buf = Vec::new();
+		put_u32(
+			&mut buf,
+			SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_PERMISSIONS | SSH_FILEXFER_ATTR_ACMODTIME,
+		);
+		put_u64(&mut buf, meta.len());
+		put_u32(&mut buf, meta.mode());
+		put_u32(&mut buf, meta.atime() as u32);
+		put_u32(&mut buf, meta.mtime() as u32);
+		buf
+	}
+
+	/// Minimal stand-in for `sftp-server` serving the directory `root`.
+	fn serve(root: PathBuf, stream: UnixStream) {
+		let mut reader = BufReader::new(stream.try_clone().unwrap());
+		let mut writer = stream;
+		let mut files: HashMap<Vec<u8>, File> = HashMap::new();
+		let mut dirs: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+		let mut next_handle = 0u32;
+
+		let resolve = |path: &[u8]| -> PathBuf {
+			let path = String::from_utf8_lossy(path);
+			root.join(path.trim_start_matches('/'))
+		};
+
+		loop {
+			let mut len = [0u8; 4];
+			if reader.read_exact(&mut len).is_err() {
+				return;
+			}
+			let mut body = vec![0u8; u32::from_be_bytes(len) as usize];
+			reader.read_exact(&mut body).unwrap();
+
+			let kind = body[0];
+			let mut d = Decoder(&body[1..]);
+
+			if kind == SSH_FXP_INIT {
+				let mut version = Vec::new();
+				put_u32(&mut version, 3);
+				put_string(&mut version, POSIX_RENAME);
+				put_string(&mut version, "1");
+				put_string(&mut version, FSYNC);
+				put_string(&mut version, "1");
+
+				writer
+					.write_all(&(version.len() as u32 + 1).to_be_bytes())
+					.unwrap();
+				writer.write_all(&[SSH_FXP_VERSION]).unwrap();
+				writer.write_all(&version).unwrap();
+				continue;
+			}
+
+			let id = d.u32().unwrap();
+			let status = |result: io::Result<()>| -> (u8, Vec<u8>) {
+				let mut buf = Vec::new();
+				let code = match &result {
+					Ok(()) => SSH_FX_OK,
+					Err(err) if err.kind() == io::ErrorKind::NotFound => SSH_FX_NO_SUCH_FILE,
+					Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => SSH_FX_EOF,
+					Err(_) => 4,
+				};
+				put_u32(&mut buf, code);
+				put_string(
+					&mut buf,
+					result.err().map(|e| e.to_string()).unwrap_or_default(),
+				);
+				put_string(&mut buf, "");
+				(SSH_FXP_STATUS, buf)
+			};
+			let handle = |next_handle: &mut u32| {
+				*next_handle += 1;
+				next_handle.to_string().into_bytes()
+			};
+
+			let (kind, payload) = match kind {
+				SSH_FXP_OPEN => {
+					let path = resolve(d.string().unwrap());
+					let flags = d.u32().unwrap();
+					let file = OpenOptions::new()
+						.read(flags & SSH_FXF_READ != 0)
+						.write(flags & SSH_FXF_WRITE != 0)
+						.create(flags & SSH_FXF_CREAT != 0 && flags & SSH_FXF_EXCL == 0)
+						.create_new(flags & SSH_FXF_EXCL != 0)
+						.truncate(flags & SSH_FXF_TRUNC != 0)
+						.open(path);
+
+					match file {
+						Ok(file) => {
+							let handle = handle(&mut next_handle);
+							files.insert(handle.clone(), file);
+							let mut buf = Vec::new();
+							put_string(&mut buf, handle);
+							(SSH_FXP_HANDLE, buf)
+						}
+						Err(err) => status(Err(err)),
+					}
+				}
+				SSH_FXP_CLOSE => {
+					let handle = d.string().unwrap();
+					files.remove(handle);
+					dirs.remove(handle);
+					status(Ok(()))
+				}
+				SSH_FXP_READ => {
+					let file = files.get_mut(d.string().unwrap()).unwrap();
+					let offset = d.u64().unwrap();
+					let len = d.u32().unwrap();
+
+					file.seek(SeekFrom::Start(offset)).unwrap();
+					let mut data = Vec::new();
+					file.take(u64::from(len)).read_to_end(&mut data).unwrap();
+
+					if data.is_empty() {
+						status(Err(io::ErrorKind::UnexpectedEof.into()))
+					} else {
+						let mut buf = Vec::new();
+						put_string(&mut buf, data);
+						(SSH_FXP_DATA, buf)
+					}
+				}
+				SSH_FXP_WRITE => {
+					let file = files.get_mut(d.string().unwrap()).unwrap();
+					let offset = d.u64().unwrap();
+					let data = d.string().unwrap();
+
+					file.seek(SeekFrom::Start(offset)).unwrap();
+					status(file.write_all(data))
+				}
+				SSH_FXP_OPENDIR => match std::fs::read_dir(resolve(d.string().unwrap())) {
+					Ok(entries) => {
+						let mut names = Vec::new();
+						let entries: Vec<_> = entries.map(|e| e.unwrap()).collect();
+						put_u32(&mut names, entries.len() as u32);
+						for entry in entries {
+							put_string(&mut names, entry.file_name().to_string_lossy().as_bytes());
+							put_string(&mut names, "");
+							names.extend(attrs(&entry.metadata().unwrap()));
+						}
+
+						let handle = handle(&mut next_handle);
+						dirs.insert(handle.clone(), names);
+						let mut buf = Vec::new();
+						put_string(&mut buf, handle);
+						(SSH_FXP_HANDLE, buf)
+					}
+					Err(err) => status(Err(err)),
+				},
+				SSH_FXP_READDIR => {
+					let names = dirs.get_mut(d.string().unwrap()).unwrap();
+					if names.is_empty() {
+						status(Err(io::ErrorKind::UnexpectedEof.into()))
+					} else {
+						(SSH_FXP_NAME, std::mem::take(names))
+					}
+				}
+				SSH_FXP_REMOVE => status(std::fs::remove_file(resolve(d.string().unwrap()))),
+				SSH_FXP_MKDIR => status(std::fs::create_dir(resolve(d.string().unwrap()))),
+				SSH_FXP_STAT => match std::fs::metadata(resolve(d.string().unwrap())) {
+					Ok(meta) => (SSH_FXP_ATTRS, attrs(&meta)),
+					Err(err) => status(Err(err)),
+				},
+				SSH_FXP_EXTENDED => match d.string().unwrap() {
+					name if name == POSIX_RENAME.as_bytes() => {
+						let from = resolve(d.string().unwrap());
+						let to = resolve(d.string().unwrap());
+						status(std::fs::rename(from, to))
+					}
+					name if name == FSYNC.as_bytes() => {
+						status(files[d.string().unwrap()].sync_all())
+					}
+					_ => status(Err(io::ErrorKind::Unsupported.into())),
+				},
+				_ => status(Err(io::ErrorKind::Unsupported.into())),
+			};
+
+			writer
+				.write_all(&(payload.len() as u32 + 5).to_be_bytes())
+				.unwrap();
+			writer.write_all(&[kind]).unwrap();
+			writer.write_all(&id.to_be_bytes()).unwrap();
+			writer.write_all(&payload).unwrap();
+		}
+	}
+
+	fn connect(root: &Path) -> Sftp {
+		let (client, server) = UnixStream::pair().unwrap();
+		let root = root.to_path_buf();
+		std::thread::spawn(move || serve(root, server));
+
+		let session = Session::new(
+			Box::new(client.try_clone().unwrap()),
+			Box::new(client),
+			None,
+		)
+		.unwrap();
+
+		Sftp::with_session("sftp://test/repo".to_string(), "/repo", session).unwrap()
+	}
+
+	#[test]
+	fn sftp() {
+		let root = std::env::temp_dir().join(format!("dechst-sftp-{}", Id::random()));
+		std::fs::create_dir_all(&root).unwrap();
+
+		let mut backend = connect(&root);
+		assert!(backend.verify().unwrap_err().is_not_found());
+
+		backend.create().unwrap();
+		backend.verify().unwrap();
+
+		let id = Id::random();
+		let bytes: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+		backend.write_all(ObjectKind::Pack, &id, &bytes).unwrap();
+		assert_eq!(backend.meta(ObjectKind::Pack, &id).unwrap().len, 100_000);
+		assert_eq!(backend.read_to_end(ObjectKind::Pack, &id).unwrap(), bytes);
+
+		let mut buf = [0u8; 4];
+		assert_eq!(
+			backend
+				.read_at(ObjectKind::Pack, &id, 99_996, &mut buf)
+				.unwrap(),
+			4
+		);
+		assert_eq!(buf, bytes[99_996..]);
+		assert!(backend
+			.read_at(ObjectKind::Pack, &id, 99_997, &mut buf)
+			.is_err());
+
+		let ids: Vec<_> = backend
+			.iter(ObjectKind::Pack)
+			.unwrap()
+			.collect::<Result<_>>()
+			.unwrap();
+		assert_eq!(ids, [id]);
+		assert_eq!(backend.iter(ObjectKind::Index).unwrap().count(), 0);
+		assert_eq!(backend.iter(ObjectKind::Config).unwrap().count(), 1);
+
+		// Same structure as local repositories
+		let local = Local::new(root.join("repo"));
+		local.verify().unwrap();
+		assert_eq!(local.read_to_end(ObjectKind::Pack, &id).unwrap(), bytes);
+
+		let leftover = root
+			.join("repo")
+			.join(ObjectKind::Snapshot.name())
+			.join(".tmp-x");
+		std::fs::write(leftover, b"").unwrap();
+		assert_eq!(backend.clean(Duration::ZERO).unwrap(), 1);
+
+		backend.remove(ObjectKind::Pack, &id).unwrap();
+		assert!(backend
+			.exists(ObjectKind::Pack, &id)
+			.unwrap_err()
+			.is_not_found());
+
+		std::fs::remove_dir_all(root).unwrap();
+	}
+
+	#[test]
+	fn option_like_url() {
+		assert!(Sftp::connect("sftp://-oProxyCommand=false/repo").is_err());
+		assert!(Sftp::connect("sftp://-oProxyCommand=false@host/repo").is_err());
+	}
+}
@@ -2,12 +2,10 @@
 //! - Move out processing steps into separate crates
 //! - Save id within tagged chunk to verify it is correct
 //! - Make passphrase derivative function generic
-//! - Way to get a locked repo without writing a lock to backend (for append/readonly systems)
 //! - Allows stdin as source
 //! - Error Correction Algorithm? (Reed-Solomon)
 //! - Check chunk size after compression; if its larger do not compress
 //! - Allow selection of compression alg depending on mime/filetype, size ...
-//! - Sharding config (directory spliting of packs e.g. [2] => 02/123123312.., [2, 2] => 02/12/12312..) (https://kopia.io/docs/advanced/sharding/)
 //! - Save attr(5) attributes on unix with `xattr`
 //! - Move path into node_path and make separate os/backen path (RawOsString)
 #![feature(adt_const_params, generic_const_exprs)]
@@ -55,29 +53,4 @@ pub mod obj;
 pub mod process;
 pub mod repo;
 pub mod source;
-
-mod ideas {
-	use crate::obj::tree::node::Node;
-
-	pub trait Target {
-		type Error: std::error::Error;
-		type Item;
-		type Write;
-
-		type Iter: Iterator<Item = Result<Self::Item, Self::Error>>;
-
-		fn iter(&self, item: Option<&Self::Item>) -> Result<Self::Iter, Self::Error>;
-		fn write(&self, item: &Self::Item) -> Result<Self::Write, Self::Error>;
-		fn node(&self, item: &Self::Item) -> Result<Node, Self::Error>;
-	}
-
-	#[derive(Debug, Clone, Copy)]
-	pub enum RestoreMode {
-		// Only create new files but dont touch existing ones
-		OnlyNew,
-		// Update exiting files
-		OnlyExisting,
-		// Restore + Delete any files not included in the backup
-		Clean,
-	}
-}
+pub mod target;
@@ -38,3 +38,34 @@ pub struct BlobEntry {
 	pub processed_len: u32,
 	pub unprocessed_len: u32,
 }
+
+/// Fixtures shared by the tests of the index users.
+#[cfg(test)]
+pub(crate) mod test {
+	use super::*;
+
+	/// Returns an id consisting of `byte` only.
+	pub fn id(byte: u8) -> Id {
+		Id([byte; crate::id::WIDTH])
+	}
+
+	/// Returns the entry of the pack `id(pack)` containing data blobs with the
+	/// ids `id(blob)` of 10 bytes each.
+	pub fn pack(pack: u8, blobs: &[u8]) -> PackEntry {
+		PackEntry {
+			id: id(pack),
+			blobs: blobs
+				.iter()
+				.enumerate()
+				.map(|(i, &blob)| BlobEntry {
+					id: id(blob),
+					kind: BlobKind::Data,
+					offset: i as u32 * 10,
+					processed_len: 10,
+					unprocessed_len: 5,
+				})
+				.collect(),
+			..Default::default()
+		}
+	}
+}
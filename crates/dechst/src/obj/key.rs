@@ -1,3 +1,5 @@
+use std::fmt;
+
 use argon2::{Argon2, ParamsBuilder, PasswordHasher};
 use chrono::{DateTime, Utc};
 use rand::RngCore;
@@ -6,9 +8,52 @@ use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 use crate::obj::{ObjectKind, RepoObject};
 use crate::os::User;
-use crate::process::encrypt::Encryption;
+use crate::process::encrypt::{EncryptError, Encryption};
 use crate::process::format::{Format, Formatter};
 
+#[derive(Debug)]
+pub enum KeyError {
+	/// The user key could not be derived from the password
+	Derive(argon2::password_hash::Error),
+	Encrypt(EncryptError),
+	/// The decrypted key can not be read
+	///
+	/// As the encryption is not authenticated, this is the only sign of a
+	/// wrong password.
+	WrongPassword,
+}
+
+impl From<argon2::password_hash::Error> for KeyError {
+	fn from(value: argon2::password_hash::Error) -> Self {
+		Self::Derive(value)
+	}
+}
+
+impl From<EncryptError> for KeyError {
+	fn from(value: EncryptError) -> Self {
+		Self::Encrypt(value)
+	}
+}
+
+impl fmt::Display for KeyError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Derive(err) => write!(f, "Failed to derive the user key: {err}"),
+			Self::Encrypt(_) => f.write_str("Failed to decrypt the key"),
+			Self::WrongPassword => f.write_str("Wrong password"),
+		}
+	}
+}
+
+impl std::error::Error for KeyError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Encrypt(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
 #[serde_with::apply(
 	Option => #[serde(default, skip_serializing_if = "Option::is_none")],
 	Vec => #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -169,17 +214,14 @@ impl Default for EncryptOptions {
 }
 
 impl EncryptOptions {
-	pub fn to_argon2_builder(self) -> argon2::ParamsBuilder {
+	pub fn to_argon2_builder(self) -> Result<argon2::ParamsBuilder, argon2::Error> {
 		let mut params = ParamsBuilder::new();
 		params
-			.m_cost(self.mem_cost)
-			.unwrap()
-			.t_cost(self.time_cost)
-			.unwrap()
-			.p_cost(self.parallel_cost)
-			.unwrap();
+			.m_cost(self.mem_cost)?
+			.t_cost(self.time_cost)?
+			.p_cost(self.parallel_cost)?;
 
-		params
+		Ok(params)
 	}
 }
 
@@ -225,23 +267,25 @@ impl EncryptedKey {
 		}
 	}
 
-	pub fn decrypt(&self, user_key: &[u8]) -> Key {
+	pub fn decrypt(&self, user_key: &[u8]) -> Result<Key, KeyError> {
 		let salt_hex = Zeroizing::new(hex::encode(self.salt));
 
 		let key_bytes =
-			Self::gen_key_bytes(self.opts, &salt_hex, user_key, self.encryption.key_length())
-				.unwrap();
+			Self::gen_key_bytes(self.opts, &salt_hex, user_key, self.encryption.key_length())?;
 
 		let decrypted_bytes = self
 			.encryption
-			.decrypt_bytes(key_bytes.as_bytes(), &self.encrypted_bytes)
-			.unwrap();
+			.decrypt_bytes(key_bytes.as_bytes(), &self.encrypted_bytes)?;
 
-		Formatter::Cbor.parse(&decrypted_bytes).unwrap()
+		Formatter::Cbor
+			.parse(&decrypted_bytes)
+			.map_err(|_| KeyError::WrongPassword)
 	}
 
-	pub fn try_unencrypted(&self) -> Key {
-		Formatter::Cbor.parse(&self.encrypted_bytes).unwrap()
+	pub fn try_unencrypted(&self) -> Result<Key, KeyError> {
+		Formatter::Cbor
+			.parse(&self.encrypted_bytes)
+			.map_err(|_| KeyError::WrongPassword)
 	}
 
 	fn gen_key_bytes<'a, 'b>(
@@ -250,14 +294,15 @@ impl EncryptedKey {
 		user_key: &'b [u8],
 		key_length: u32,
 	) -> Result<argon2::password_hash::Output, argon2::password_hash::Error> {
-		let mut params = opts.to_argon2_builder();
-		params.output_len(key_length as usize).unwrap();
-		let params = params.params().unwrap();
+		let mut params = opts.to_argon2_builder()?;
+		params.output_len(key_length as usize)?;
+		let params = params.params()?;
 
 		let algo = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
 
-		algo.hash_password(user_key, salt_hex)
-			.map(|x| x.hash.unwrap())
+		algo.hash_password(user_key, salt_hex)?
+			.hash
+			.ok_or(argon2::password_hash::Error::Password)
 	}
 }
 
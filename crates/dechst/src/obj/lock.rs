@@ -1,7 +1,10 @@
-use chrono::{DateTime, Utc};
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::obj::{ObjectKind, RepoObject};
+use crate::os::raw::RawOsString;
 use crate::os::User;
 use crate::repo::marker::LockMarker;
 
@@ -46,6 +49,16 @@ pub enum LockAccess {
 	Exclusive,
 }
 
+impl LockAccess {
+	/// Returns `true` if `self` and `other` can not be held at the same time.
+	pub const fn conflicts(self, other: Self) -> bool {
+		matches!(
+			(self, other),
+			(Self::Exclusive, Self::Shared | Self::Exclusive) | (Self::Shared, Self::Exclusive)
+		)
+	}
+}
+
 #[serde_with::apply(
 	Option => #[serde(default, skip_serializing_if = "Option::is_none")],
 	Vec => #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -59,6 +72,27 @@ pub struct LockState {
 	pub pack: LockAccess,
 }
 
+impl LockState {
+	/// Returns `true` if the access to any object kind conflicts with `other`.
+	pub const fn conflicts(&self, other: &Self) -> bool {
+		self.config.conflicts(other.config)
+			|| self.index.conflicts(other.index)
+			|| self.key.conflicts(other.key)
+			|| self.snapshot.conflicts(other.snapshot)
+			|| self.pack.conflicts(other.pack)
+	}
+
+	/// Returns `true` if `self` grants at least the access of `other` to every
+	/// object kind.
+	pub fn contains(&self, other: &Self) -> bool {
+		self.config >= other.config
+			&& self.index >= other.index
+			&& self.key >= other.key
+			&& self.snapshot >= other.snapshot
+			&& self.pack >= other.pack
+	}
+}
+
 impl<CONFIG, INDEX, KEY, SNAPSHOT, PACK> From<LockMarker<CONFIG, INDEX, KEY, SNAPSHOT, PACK>>
 	for LockState
 where
@@ -88,15 +122,28 @@ pub struct LockMeta {
 	pub user: User,
 	pub created: DateTime<Utc>,
 	pub pid: u32,
+	/// The last time the lock was rewritten by its holder
+	pub refreshed: Option<DateTime<Utc>>,
 }
 
 impl LockMeta {
+	/// How often a held lock is rewritten with a new timestamp.
+	///
+	/// This must stay well below [`Self::STALE_AFTER`].
+	pub const REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+	/// Locks which have not been refreshed for this long are stale.
+	///
+	/// Holders refresh their lock well within this time (see
+	/// [`Self::REFRESH_INTERVAL`]).
+	pub const STALE_AFTER: Duration = Duration::minutes(30);
+
 	#[cfg(not(target_family = "unix"))]
 	pub fn new() -> Self {
 		Self {
 			user: Default::default(),
 			created: Utc::now(),
 			pid: std::process::id(),
+			refreshed: None,
 		}
 	}
 
@@ -106,10 +153,82 @@ impl LockMeta {
 			user: Default::default(),
 			created: Utc::now(),
 			pid: std::process::id(),
+			refreshed: None,
+		}
+	}
+
+	/// Returns the time the lock was last written by its holder.
+	pub fn last_refresh(&self) -> DateTime<Utc> {
+		self.refreshed.unwrap_or(self.created)
+	}
+
+	/// Returns `true` if the lock was not refreshed for [`Self::STALE_AFTER`].
+	///
+	/// A lock created by a process on this host which no longer exists is
+	/// stale once it missed a single refresh (see [`Self::REFRESH_INTERVAL`]).
+	/// Waiting for the refresh guards against processes which are not visible
+	/// from here (e.g. in other pid namespaces sharing the hostname).
+	pub fn is_stale(&self) -> bool {
+		let age = Utc::now().signed_duration_since(self.last_refresh());
+
+		if age > Self::STALE_AFTER {
+			return true;
+		}
+
+		let refresh = Duration::from_std(Self::REFRESH_INTERVAL).unwrap_or(Self::STALE_AFTER);
+		if age <= refresh {
+			return false;
 		}
+
+		let host = RawOsString::from(whoami::hostname_os());
+		let same_host = self.user.hostname() == Some(&host);
+
+		same_host && !process_exists(self.pid)
+	}
+}
+
+impl fmt::Display for LockMeta {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let unknown = || "<unknown>".to_string();
+		let user = self
+			.user
+			.username()
+			.map_or_else(unknown, ToString::to_string);
+		let host = self
+			.user
+			.hostname()
+			.map_or_else(unknown, ToString::to_string);
+
+		write!(
+			f,
+			"{user}@{host} (pid {}, created {})",
+			self.pid,
+			self.created.format("%Y-%m-%d %H:%M:%S %Z")
+		)
 	}
 }
 
+#[cfg(target_family = "unix")]
+fn process_exists(pid: u32) -> bool {
+	// Pid `0` and negative pids address process groups
+	let pid = match libc::pid_t::try_from(pid) {
+		Ok(pid) if pid > 0 => pid,
+		_ => return true,
+	};
+
+	// SAFETY: Signal `0` only checks if the process could be signaled
+	if unsafe { libc::kill(pid, 0) } == 0 {
+		return true;
+	}
+
+	std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+#[cfg(not(target_family = "unix"))]
+const fn process_exists(_pid: u32) -> bool {
+	true
+}
+
 #[serde_with::apply(
 	Option => #[serde(default, skip_serializing_if = "Option::is_none")],
 	Vec => #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -126,3 +245,58 @@ pub struct Lock {
 impl RepoObject for Lock {
 	const KIND: ObjectKind = ObjectKind::Lock;
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn conflicts() {
+		let read = LockState::from(LockMarker::READ);
+		let write = LockState::from(LockMarker::WRITE);
+		let none = LockState::from(LockMarker::NO);
+		let forget = LockState::from(LockMarker::READ.snapshot::<Exclusive>());
+
+		assert!(!read.conflicts(&read));
+		assert!(read.conflicts(&write));
+		assert!(write.conflicts(&write));
+		assert!(!none.conflicts(&write));
+		assert!(forget.conflicts(&read));
+		assert!(!forget.conflicts(&LockState::from(LockMarker::READ.snapshot::<None>())));
+
+		assert!(write.contains(&forget));
+		assert!(forget.contains(&read));
+		assert!(!read.contains(&forget));
+	}
+
+	#[cfg(target_family = "unix")]
+	#[test]
+	fn stale() {
+		let mut child = std::process::Command::new("true").spawn().unwrap();
+		child.wait().unwrap();
+
+		let mut meta = LockMeta::new();
+		assert!(!meta.is_stale());
+
+		// The process might only be invisible from here
+		meta.pid = child.id();
+		assert!(!meta.is_stale());
+
+		let missed_refresh = Duration::from_std(LockMeta::REFRESH_INTERVAL).unwrap() * 2;
+		meta.created = Utc::now() - missed_refresh;
+		assert!(meta.is_stale());
+
+		meta.user = crate::os::unix::User {
+			hostname: Some(std::ffi::OsString::from("some-other-host").into()),
+			..Default::default()
+		}
+		.into();
+		assert!(!meta.is_stale());
+
+		meta.created = Utc::now() - LockMeta::STALE_AFTER * 2;
+		assert!(meta.is_stale());
+
+		meta.refreshed = Some(Utc::now());
+		assert!(!meta.is_stale());
+	}
+}
@@ -1,5 +1,19 @@
+//! A pack is the unit in which blobs are stored in a backend.
+//!
+//! Layout:
+//!
+//! ```text
+//! [blob 0][blob 1]...[blob n][header][header_len]
+//! ```
+//!
+//! - `blob`: Processed blob
+//! - `header`: Processed [`PackHeader`] listing all blobs in order
+//! - `header_len`: Length of `header` as `u32` (little endian)
+
 use serde::{Deserialize, Serialize};
 
+use super::blob::BlobKind;
+use super::index::BlobEntry;
 use crate::id::Id;
 use crate::obj::{ObjectKind, RepoObject};
 
@@ -21,6 +35,53 @@ pub enum HeaderEntry {
 	},
 }
 
+impl HeaderEntry {
+	pub const fn new(kind: BlobKind, id: Id, processed_len: u32, unprocessed_len: u32) -> Self {
+		match kind {
+			BlobKind::Data => Self::Data {
+				processed_len,
+				unprocessed_len,
+				id,
+			},
+			BlobKind::Tree => Self::Tree {
+				processed_len,
+				unprocessed_len,
+				id,
+			},
+		}
+	}
+
+	pub const fn kind(&self) -> BlobKind {
+		match self {
+			Self::Data { .. } => BlobKind::Data,
+			Self::Tree { .. } => BlobKind::Tree,
+		}
+	}
+
+	pub const fn id(&self) -> &Id {
+		match self {
+			Self::Data { id, .. } | Self::Tree { id, .. } => id,
+		}
+	}
+
+	pub const fn processed_len(&self) -> u32 {
+		match self {
+			Self::Data { processed_len, .. } | Self::Tree { processed_len, .. } => *processed_len,
+		}
+	}
+
+	pub const fn unprocessed_len(&self) -> u32 {
+		match self {
+			Self::Data {
+				unprocessed_len, ..
+			}
+			| Self::Tree {
+				unprocessed_len, ..
+			} => *unprocessed_len,
+		}
+	}
+}
+
 #[serde_with::apply(
 	Option => #[serde(default, skip_serializing_if = "Option::is_none")],
 	Vec => #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -30,6 +91,33 @@ pub struct PackHeader {
 	entries: Vec<HeaderEntry>,
 }
 
+impl PackHeader {
+	pub const fn new(entries: Vec<HeaderEntry>) -> Self {
+		Self { entries }
+	}
+
+	pub fn entries(&self) -> &[HeaderEntry] {
+		&self.entries
+	}
+
+	/// Returns all entries together with their offset within the pack.
+	pub fn blobs(&self) -> impl Iterator<Item = BlobEntry> + '_ {
+		self.entries.iter().scan(0u32, |offset, entry| {
+			let blob = BlobEntry {
+				id: *entry.id(),
+				kind: entry.kind(),
+				offset: *offset,
+				processed_len: entry.processed_len(),
+				unprocessed_len: entry.unprocessed_len(),
+			};
+
+			*offset += entry.processed_len();
+
+			Some(blob)
+		})
+	}
+}
+
 #[serde_with::apply(
 	Option => #[serde(default, skip_serializing_if = "Option::is_none")],
 	Vec => #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -43,6 +131,72 @@ pub struct Pack {
 	header_len: u32,
 }
 
+impl Pack {
+	/// Size of the trailing `header_len`.
+	pub const HEADER_LEN_SIZE: u32 = u32::BITS / 8;
+
+	pub fn new(blobs: Vec<u8>, header: Vec<u8>) -> Self {
+		let header_len = header.len().try_into().unwrap();
+
+		Self {
+			blobs,
+			header,
+			header_len,
+		}
+	}
+
+	pub fn blobs(&self) -> &[u8] {
+		&self.blobs
+	}
+
+	pub fn header(&self) -> &[u8] {
+		&self.header
+	}
+
+	pub const fn header_len(&self) -> u32 {
+		self.header_len
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(
+			self.blobs.len() + self.header.len() + Self::HEADER_LEN_SIZE as usize,
+		);
+
+		bytes.extend_from_slice(&self.blobs);
+		bytes.extend_from_slice(&self.header);
+		bytes.extend_from_slice(&self.header_len.to_le_bytes());
+
+		bytes
+	}
+
+	/// Splits raw pack bytes into its parts.
+	///
+	/// Returns `None` if the bytes are to short to be a valid pack.
+	pub fn from_bytes(mut bytes: Vec<u8>) -> Option<Self> {
+		let header_len = Self::parse_header_len(&bytes)?;
+
+		let header_start = bytes
+			.len()
+			.checked_sub(Self::HEADER_LEN_SIZE as usize + header_len as usize)?;
+
+		bytes.truncate(bytes.len() - Self::HEADER_LEN_SIZE as usize);
+		let header = bytes.split_off(header_start);
+
+		Some(Self {
+			blobs: bytes,
+			header,
+			header_len,
+		})
+	}
+
+	/// Parses the trailing `header_len` from the end of `bytes`.
+	pub fn parse_header_len(bytes: &[u8]) -> Option<u32> {
+		let start = bytes.len().checked_sub(Self::HEADER_LEN_SIZE as usize)?;
+
+		Some(u32::from_le_bytes(bytes[start..].try_into().ok()?))
+	}
+}
+
 impl RepoObject for Pack {
 	const KIND: ObjectKind = ObjectKind::Pack;
 }
@@ -53,6 +53,24 @@ impl Default for Snapshot {
 	}
 }
 
+impl Snapshot {
+	/// Returns the group (host + root) of the snapshot.
+	pub fn group(&self) -> SnapshotGroup {
+		SnapshotGroup {
+			host: self.user.hostname().cloned(),
+			root: self.root.clone(),
+		}
+	}
+}
+
 impl RepoObject for Snapshot {
 	const KIND: ObjectKind = ObjectKind::Snapshot;
 }
+
+/// Snapshots of the same host and root are considered to be one series of
+/// backups.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SnapshotGroup {
+	pub host: Option<RawOsString>,
+	pub root: RawOsString,
+}
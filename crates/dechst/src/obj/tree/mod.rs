@@ -14,11 +14,21 @@ pub struct Tree {
 }
 
 impl Tree {
+	pub const fn new(nodes: Vec<Node>) -> Self {
+		Self { nodes }
+	}
+
 	pub fn iter(&self) -> std::slice::Iter<'_, Node> {
 		self.nodes.iter()
 	}
 }
 
+impl FromIterator<Node> for Tree {
+	fn from_iter<T: IntoIterator<Item = Node>>(iter: T) -> Self {
+		Self::new(iter.into_iter().collect())
+	}
+}
+
 impl IntoIterator for Tree {
 	type IntoIter = std::vec::IntoIter<Node>;
 	type Item = Node;
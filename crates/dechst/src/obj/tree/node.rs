@@ -1,6 +1,6 @@
 use std::ffi::OsString;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::id::Id;
 use crate::os::raw::RawOsString;
@@ -39,10 +39,19 @@ pub enum NodeKind {
 	CharacterDevice {
 		device: u64,
 	},
+	// Flattened into `Node` these are written as `null` which the derived
+	// unit variants do not accept
+	#[serde(deserialize_with = "unit_variant")]
 	Fifo,
+	#[serde(deserialize_with = "unit_variant")]
 	Socket,
 }
 
+/// Accepts the `null` a flattened unit variant is written as.
+fn unit_variant<'de, D: Deserializer<'de>>(deserializer: D) -> Result<(), D::Error> {
+	Option::<()>::deserialize(deserializer).map(drop)
+}
+
 impl NodeKind {
 	pub fn file() -> Self {
 		Self::File { blobs: vec![] }
@@ -98,3 +107,26 @@ pub struct Node {
 	#[serde(flatten)]
 	pub meta: Metadata,
 }
+
+#[cfg(all(test, target_family = "unix"))]
+mod test {
+	use super::*;
+	use crate::process::format::{Format, Formatter};
+
+	fn node(kind: NodeKind) -> Node {
+		Node {
+			name: OsString::from("node").into(),
+			kind,
+			meta: Metadata::default(),
+		}
+	}
+
+	#[test]
+	fn unit_kinds() {
+		for kind in [NodeKind::fifo(), NodeKind::socket()] {
+			let bytes = Formatter::Cbor.format(&node(kind.clone())).unwrap();
+			let parsed: Node = Formatter::Cbor.parse(&bytes).unwrap();
+			assert_eq!(parsed.kind, kind);
+		}
+	}
+}
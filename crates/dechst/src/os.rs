@@ -20,6 +20,22 @@ pub enum User {
 	Windows(windows::User),
 }
 
+impl User {
+	pub const fn hostname(&self) -> Option<&raw::RawOsString> {
+		match self {
+			Self::Unix(user) => user.hostname.as_ref(),
+			Self::Windows(user) => user.hostname.as_ref(),
+		}
+	}
+
+	pub const fn username(&self) -> Option<&raw::RawOsString> {
+		match self {
+			Self::Unix(user) => user.username.as_ref(),
+			Self::Windows(user) => user.username.as_ref(),
+		}
+	}
+}
+
 impl From<unix::User> for User {
 	fn from(value: unix::User) -> Self {
 		Self::Unix(value)
@@ -6,7 +6,7 @@ use serde::de::Visitor;
 use serde::{Deserialize, Serialize};
 
 // TODO: Alternativly use String with escaping
-#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct RawOsString(os_str_bytes::RawOsString);
 
 impl fmt::Display for RawOsString {
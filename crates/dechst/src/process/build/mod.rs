@@ -0,0 +1,20 @@
+//! Source -> TreeBuilder -> ImTree -> Tree
+//!
+//!
+//! # Idea 1
+//! workload() -> Vec<Workload>;
+//! resolve(node_id, ids);
+//!
+//! Workload {
+//!		nodes: Vec<Node>,
+//!		OR
+//!		tree: Tree,
+//! }
+//!
+//! # Idea 2
+//!
+//! imtree.leaves().for_each({..; imtree.resolve(&node, ids)});
+//! imtree.branches().for_each({});
+pub mod pack;
+pub mod snapshot;
+pub mod tree;
@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::num::NonZeroU32;
+
+use chrono::Utc;
+
+use crate::id::Id;
+use crate::obj::blob::BlobKind;
+use crate::obj::index::{BlobEntry, PackEntry};
+use crate::obj::pack::{HeaderEntry, Pack, PackHeader};
+use crate::process::format::{Format, FormatError, Formatter};
+use crate::process::identify::{Identifier, Identify, IdentifyError};
+use crate::process::pipeline::{ChunkPipeline, PipelineError};
+
+#[derive(Debug)]
+pub enum PackError {
+	Identify(IdentifyError),
+	Format(FormatError),
+	Pipeline(PipelineError),
+	/// The blob or pack exceeds the maximum size of a pack (`u32::MAX`)
+	TooLarge,
+}
+
+impl From<IdentifyError> for PackError {
+	fn from(value: IdentifyError) -> Self {
+		Self::Identify(value)
+	}
+}
+
+impl From<FormatError> for PackError {
+	fn from(value: FormatError) -> Self {
+		Self::Format(value)
+	}
+}
+
+impl From<PipelineError> for PackError {
+	fn from(value: PipelineError) -> Self {
+		Self::Pipeline(value)
+	}
+}
+
+impl fmt::Display for PackError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Identify(inner) => write!(f, "Identify: {inner}"),
+			Self::Format(inner) => write!(f, "Format: {inner}"),
+			Self::Pipeline(inner) => write!(f, "Pipeline: {inner}"),
+			Self::TooLarge => f.write_str("Pack exceeds the maximum size"),
+		}
+	}
+}
+
+impl std::error::Error for PackError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Identify(s) => Some(s),
+			Self::Format(s) => Some(s),
+			Self::Pipeline(s) => Some(s),
+			Self::TooLarge => None,
+		}
+	}
+}
+
+pub type Result<T, E = PackError> = std::result::Result<T, E>;
+
+/// A finished pack ready to be written to the backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltPack {
+	pub id: Id,
+	pub bytes: Vec<u8>,
+	/// Entry to be added to an index
+	pub entry: PackEntry,
+}
+
+/// Collects processed blobs into a single [`Pack`].
+///
+/// Blobs are appended until [`PackBuilder::is_full`] signals that the target
+/// size was reached, after which the pack should be [finished](PackBuilder::finish)
+/// and written.
+#[derive(Debug)]
+pub struct PackBuilder {
+	pipeline: ChunkPipeline,
+	identifier: Identifier,
+	target_size: u32,
+	blobs: Vec<u8>,
+	entries: Vec<HeaderEntry>,
+	ids: HashSet<Id>,
+}
+
+impl PackBuilder {
+	pub const DEFAULT_TARGET_SIZE: u32 = 16 * 1024 * 1024;
+
+	pub fn new(pipeline: ChunkPipeline, identifier: Identifier) -> Self {
+		Self {
+			pipeline,
+			identifier,
+			target_size: Self::DEFAULT_TARGET_SIZE,
+			blobs: Vec::new(),
+			entries: Vec::new(),
+			ids: HashSet::new(),
+		}
+	}
+
+	pub const fn target_size(mut self, target_size: u32) -> Self {
+		self.target_size = target_size;
+		self
+	}
+
+	/// Processes `bytes` and appends them as a blob with the id `id`.
+	///
+	/// Returns the entry of the blob within this pack.
+	pub fn add(&mut self, kind: BlobKind, id: Id, bytes: &[u8]) -> Result<BlobEntry> {
+		let unprocessed_len = bytes.len().try_into().map_err(|_| PackError::TooLarge)?;
+		let processed = self.pipeline.process(bytes)?;
+
+		self.add_processed(kind, id, &processed, unprocessed_len)
+	}
+
+	/// Appends already processed `bytes` as a blob with the id `id`.
+	pub fn add_processed(
+		&mut self,
+		kind: BlobKind,
+		id: Id,
+		bytes: &[u8],
+		unprocessed_len: u32,
+	) -> Result<BlobEntry> {
+		let offset = self.len();
+		let processed_len: u32 = bytes.len().try_into().map_err(|_| PackError::TooLarge)?;
+
+		offset
+			.checked_add(processed_len)
+			.ok_or(PackError::TooLarge)?;
+
+		self.blobs.extend_from_slice(bytes);
+		self.entries
+			.push(HeaderEntry::new(kind, id, processed_len, unprocessed_len));
+		self.ids.insert(id);
+
+		Ok(BlobEntry {
+			id,
+			kind,
+			offset,
+			processed_len,
+			unprocessed_len,
+		})
+	}
+
+	pub fn contains(&self, id: &Id) -> bool {
+		self.ids.contains(id)
+	}
+
+	/// Length of all blobs in bytes.
+	pub const fn len(&self) -> u32 {
+		// Can not overflow as it is checked in `add_processed`
+		self.blobs.len() as u32
+	}
+
+	pub const fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub const fn is_full(&self) -> bool {
+		self.len() >= self.target_size
+	}
+
+	/// Writes the header and returns the finished pack.
+	pub fn finish(self) -> Result<BuiltPack> {
+		let header = PackHeader::new(self.entries);
+		let blobs = header.blobs().collect();
+
+		let header = Formatter::Cbor.format(&header)?;
+		let header = self.pipeline.process(&header)?;
+
+		let bytes = Pack::new(self.blobs, header).to_bytes();
+		let size: u32 = bytes.len().try_into().map_err(|_| PackError::TooLarge)?;
+		let id = self.identifier.identify(&self.pipeline.key, &bytes)?;
+
+		let entry = PackEntry {
+			id,
+			blobs,
+			time: Some(Utc::now()),
+			size: NonZeroU32::new(size),
+		};
+
+		Ok(BuiltPack { id, bytes, entry })
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::obj::key::Key;
+	use crate::process::chunk::ChunkerParams;
+	use crate::process::compress::CompressionParams;
+	use crate::process::encrypt::EncryptionParams;
+	use crate::process::identify::IdentifierParams;
+	use crate::process::pipeline::{unprocess, unprocess_bytes};
+	use crate::process::verify::VerifierParams;
+	use crate::process::{Instanciate, ProcessOptions};
+
+	#[test]
+	fn pack_roundtrip() -> std::result::Result<(), Box<dyn std::error::Error>> {
+		let opts = ProcessOptions {
+			chunker: ChunkerParams::FastCdc(Default::default()),
+			identifier: IdentifierParams::Blake3,
+			compression: CompressionParams::Brotli,
+			encryption: EncryptionParams::ChaCha20,
+			verifier: VerifierParams::Blake3,
+		};
+		let key = Key::random();
+		let identifier = opts.identifier.create();
+
+		let blobs: [&[u8]; 3] = [b"first", b"second blob", b"third"];
+
+		let mut builder = PackBuilder::new(
+			ChunkPipeline::new(opts, key.clone()),
+			opts.identifier.create(),
+		)
+		.target_size(16);
+
+		for (i, blob) in blobs.iter().enumerate() {
+			let kind = if i == 0 {
+				BlobKind::Tree
+			} else {
+				BlobKind::Data
+			};
+			let id = identifier.identify(&key, blob)?;
+			builder.add(kind, id, blob)?;
+		}
+
+		assert!(builder.is_full());
+
+		let pack = builder.finish()?;
+		assert_eq!(pack.id, identifier.identify(&key, &pack.bytes)?);
+		assert_eq!(pack.entry.blobs.len(), blobs.len());
+		assert_eq!(pack.entry.blobs[0].kind, BlobKind::Tree);
+
+		let parsed = Pack::from_bytes(pack.bytes.clone()).unwrap();
+		let header: PackHeader = unprocess(Formatter::Cbor, &key, parsed.header())?;
+		assert_eq!(header.blobs().collect::<Vec<_>>(), pack.entry.blobs);
+
+		for (entry, blob) in pack.entry.blobs.iter().zip(blobs) {
+			let start = entry.offset as usize;
+			let end = start + entry.processed_len as usize;
+
+			let bytes = unprocess_bytes(&Formatter::Cbor, &key, &pack.bytes[start..end])?;
+			assert_eq!(bytes, blob);
+			assert_eq!(entry.unprocessed_len as usize, blob.len());
+		}
+
+		Ok(())
+	}
+}
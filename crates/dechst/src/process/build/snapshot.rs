@@ -0,0 +1,393 @@
+use std::fmt;
+use std::io::Read;
+
+use chrono::Utc;
+
+use super::pack::{PackBuilder, PackError};
+use super::tree::im::{self, TreeErrorKind};
+use super::tree::TreeBuilder;
+use crate::backend::BackendWrite;
+use crate::id::{Id, Idd};
+use crate::obj::blob::BlobKind;
+use crate::obj::index::{Index, PackEntry};
+use crate::obj::key::Key;
+use crate::obj::lock::sealed::{AccessExclusive, AccessShared};
+use crate::obj::snapshot::Snapshot;
+use crate::obj::tree::node::NodeKind;
+use crate::obj::tree::Tree;
+use crate::os::raw::RawOsString;
+use crate::path::PathBuf;
+use crate::process::chunk::Chunker;
+use crate::process::format::{Format, FormatError, Formatter};
+use crate::process::identify::{Identifier, Identify, IdentifyError};
+use crate::process::pipeline::ChunkPipeline;
+use crate::process::{Instanciate, ProcessOptions};
+use crate::repo::index::{IndexUpdate, MasterIndex};
+use crate::repo::pack::PackUpdate;
+use crate::repo::snapshot::SnapshotUpdate;
+use crate::repo::{LockedRepo, RepoError};
+use crate::source::{Item, Source};
+
+#[derive(Debug)]
+pub enum BuildError<E> {
+	Source(E),
+	Tree { kind: TreeErrorKind, path: PathBuf },
+	Read(std::io::Error),
+	Identify(IdentifyError),
+	Format(FormatError),
+	Pack(PackError),
+	Repo(RepoError),
+}
+
+impl<E> From<IdentifyError> for BuildError<E> {
+	fn from(value: IdentifyError) -> Self {
+		Self::Identify(value)
+	}
+}
+
+impl<E> From<FormatError> for BuildError<E> {
+	fn from(value: FormatError) -> Self {
+		Self::Format(value)
+	}
+}
+
+impl<E> From<PackError> for BuildError<E> {
+	fn from(value: PackError) -> Self {
+		Self::Pack(value)
+	}
+}
+
+impl<E: fmt::Display> fmt::Display for BuildError<E> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Source(inner) => write!(f, "Source: {inner}"),
+			Self::Tree { kind, path } => write!(f, "Tree: {kind:?} at `{path}`"),
+			Self::Read(inner) => write!(f, "Read: {inner}"),
+			Self::Identify(inner) => write!(f, "Identify: {inner}"),
+			Self::Format(inner) => write!(f, "Format: {inner}"),
+			Self::Pack(inner) => write!(f, "Pack: {inner}"),
+			Self::Repo(inner) => write!(f, "Repository: {inner}"),
+		}
+	}
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for BuildError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Source(s) => Some(s),
+			Self::Read(s) => Some(s),
+			Self::Identify(s) => Some(s),
+			Self::Format(s) => Some(s),
+			Self::Pack(s) => Some(s),
+			Self::Repo(s) => Some(s),
+			Self::Tree { .. } => None,
+		}
+	}
+}
+
+pub type Result<T, E> = std::result::Result<T, BuildError<E>>;
+
+/// Creates a new [`Snapshot`] by storing all items of a [`Source`] in a
+/// repository.
+///
+/// Source -> TreeBuilder -> ImTree -> Tree(s) -> Snapshot
+#[derive(Default, Debug, Clone)]
+pub struct SnapshotBuilder {
+	root: RawOsString,
+	parent: Option<Id>,
+	tags: Vec<String>,
+	name: Option<String>,
+	description: Option<String>,
+}
+
+impl SnapshotBuilder {
+	pub fn new<R: Into<RawOsString>>(root: R) -> Self {
+		Self {
+			root: root.into(),
+			..Default::default()
+		}
+	}
+
+	pub const fn parent(mut self, parent: Id) -> Self {
+		self.parent = Some(parent);
+		self
+	}
+
+	pub fn tag<S: Into<String>>(mut self, tag: S) -> Self {
+		self.tags.push(tag.into());
+		self
+	}
+
+	pub fn tags<I, S>(mut self, tags: I) -> Self
+	where
+		I: IntoIterator<Item = S>,
+		S: Into<String>,
+	{
+		self.tags.extend(tags.into_iter().map(Into::into));
+		self
+	}
+
+	pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+		self.name = Some(name.into());
+		self
+	}
+
+	pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+		self.description = Some(description.into());
+		self
+	}
+
+	pub fn build<S, B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
+		self,
+		repo: &mut LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
+		source: &S,
+	) -> Result<Idd<Snapshot>, S::Error>
+	where
+		S: Source,
+		S::Read: Read + 'static,
+		B: BackendWrite,
+		CONFIG: AccessShared,
+		INDEX: AccessExclusive,
+		SNAPSHOT: AccessExclusive,
+		PACK: AccessExclusive,
+	{
+		let time = Utc::now();
+
+		log::debug!("Scanning source");
+		let tree = walk(source).build().map_err(|err| BuildError::Tree {
+			kind: err.kind(),
+			path: err.path().clone(),
+		})?;
+
+		log::debug!("Loading index");
+		let index = MasterIndex::load(repo).map_err(BuildError::Repo)?;
+
+		log::debug!("Storing source contents");
+		let (tree, packs) = {
+			let process = repo.config().process;
+			let key = repo.key().clone();
+
+			let mut store = Store {
+				chunker: process.chunker.create(),
+				identifier: process.identifier.create(),
+				pack: PackBuilder::new(repo.pipeline(), process.identifier.create()),
+				process,
+				key,
+				index,
+				packs: Vec::new(),
+				repo: &mut *repo,
+				source,
+			};
+
+			let tree = store.store_tree(tree)?;
+			store.flush()?;
+
+			(tree, store.packs)
+		};
+
+		if !packs.is_empty() {
+			log::debug!("Writing index for {} pack(s)", packs.len());
+
+			let index = Index {
+				packs,
+				..Default::default()
+			};
+
+			repo.index_write(&index).map_err(BuildError::Repo)?;
+		}
+
+		let Self {
+			root,
+			parent,
+			tags,
+			name,
+			description,
+		} = self;
+
+		let snapshot = Snapshot {
+			time,
+			parent,
+			tree,
+			root,
+			tags,
+			name,
+			description,
+			..Default::default()
+		};
+
+		let id = repo.snapshot_write(&snapshot).map_err(BuildError::Repo)?;
+
+		Ok(id.idd(snapshot))
+	}
+}
+
+/// Collects all items of `source` into a [`TreeBuilder`].
+///
+/// Items which can not be accessed are logged and skipped.
+fn walk<S: Source>(source: &S) -> TreeBuilder<S::Item> {
+	fn walk_into<S: Source>(
+		source: &S,
+		path: &mut PathBuf,
+		tree: &mut TreeBuilder<S::Item>,
+		item: Option<&S::Item>,
+	) {
+		let iter = match source.iter(item) {
+			Ok(iter) => iter,
+			Err(err) => {
+				log::warn!("Failed to list `{path}`: {err}");
+				return;
+			}
+		};
+
+		for item in iter {
+			let item = match item {
+				Ok(item) => item,
+				Err(err) => {
+					log::warn!("Failed to get item in `{path}`: {err}");
+					continue;
+				}
+			};
+
+			let node = match source.node(&item) {
+				Ok(node) => node,
+				Err(err) => {
+					log::warn!("Failed to get node in `{path}`: {err}");
+					continue;
+				}
+			};
+
+			let descend = item.can_descend();
+			let sgmt = node.name.clone();
+
+			tree.add(path, node, item.clone());
+
+			if descend {
+				path.push(sgmt);
+				walk_into(source, path, tree, Some(&item));
+				let _ = path.pop();
+			}
+		}
+	}
+
+	let mut tree = TreeBuilder::default();
+	walk_into(source, &mut PathBuf::new(), &mut tree, None);
+	tree
+}
+
+struct Store<'a, R, S> {
+	chunker: Chunker,
+	identifier: Identifier,
+	pack: PackBuilder,
+	process: ProcessOptions,
+	key: Key,
+	/// Blobs which are already stored (including the ones of this run)
+	index: MasterIndex,
+	/// Packs written during this run
+	packs: Vec<PackEntry>,
+	repo: &'a mut R,
+	source: &'a S,
+}
+
+impl<'a, R, S> Store<'a, R, S>
+where
+	R: PackUpdate,
+	S: Source,
+	S::Read: Read + 'static,
+{
+	fn store_tree(&mut self, tree: im::Tree<S::Item>) -> Result<Id, S::Error> {
+		let mut nodes = Vec::with_capacity(tree.len());
+
+		for node in tree {
+			let node = match node {
+				im::Node::Leaf { mut node, item } => {
+					if let NodeKind::File { blobs } = &mut node.kind {
+						let read = match self.source.read(&item) {
+							Ok(read) => read,
+							Err(err) => {
+								log::warn!("Failed to read `{}`: {err}", node.name);
+								continue;
+							}
+						};
+
+						*blobs = self.store_file(read)?;
+					}
+
+					node
+				}
+				im::Node::Branch { mut node, tree, .. } => {
+					let subtree = self.store_tree(*tree)?;
+					node.kind = NodeKind::Directory {
+						subtree: Some(subtree),
+					};
+
+					node
+				}
+			};
+
+			nodes.push(node);
+		}
+
+		// Makes the tree (and thereby its id) independent of the iteration
+		// order of the source.
+		nodes.sort_by(|a, b| a.name.cmp(&b.name));
+
+		let bytes = Formatter::Cbor.format(&Tree::new(nodes))?;
+
+		self.store_blob(BlobKind::Tree, &bytes)
+	}
+
+	fn store_file(&mut self, read: S::Read) -> Result<Vec<Id>, S::Error> {
+		let mut blobs = Vec::new();
+
+		for chunk in self.chunker.chunk(read) {
+			let chunk = chunk.map_err(BuildError::Read)?;
+			blobs.push(self.store_blob(BlobKind::Data, &chunk)?);
+		}
+
+		Ok(blobs)
+	}
+
+	fn store_blob(&mut self, kind: BlobKind, bytes: &[u8]) -> Result<Id, S::Error> {
+		let id = self.identifier.identify(&self.key, bytes)?;
+
+		// Deduplicate already stored blobs
+		if self.index.contains(&id) || self.pack.contains(&id) {
+			return Ok(id);
+		}
+
+		self.pack.add(kind, id, bytes)?;
+
+		if self.pack.is_full() {
+			self.flush()?;
+		}
+
+		Ok(id)
+	}
+
+	/// Writes the current pack (if not empty).
+	fn flush(&mut self) -> Result<(), S::Error> {
+		if self.pack.is_empty() {
+			return Ok(());
+		}
+
+		let pack = std::mem::replace(
+			&mut self.pack,
+			PackBuilder::new(
+				ChunkPipeline::new(self.process, self.key.clone()),
+				self.process.identifier.create(),
+			),
+		)
+		.finish()?;
+
+		log::debug!("Writing pack {} ({} bytes)", pack.id, pack.bytes.len());
+
+		self.repo
+			.pack_write(&pack.id, &pack.bytes)
+			.map_err(BuildError::Repo)?;
+
+		self.index.insert(&pack.entry);
+		self.packs.push(pack.entry);
+
+		Ok(())
+	}
+}
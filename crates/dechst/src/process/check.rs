@@ -0,0 +1,377 @@
+//! Verifies the integrity of a repository.
+//!
+//! Without reading any data only the structure, the indices and the trees of
+//! all snapshots are checked. Reading the data additionally downloads packs
+//! and verifies every blob within them.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::backend::BackendWrite;
+use crate::id::Id;
+use crate::obj::index::PackEntry;
+use crate::obj::key::Key;
+use crate::obj::lock::sealed::AccessShared;
+use crate::obj::pack::{Pack, PackHeader};
+use crate::obj::tree::node::NodeKind;
+use crate::obj::tree::Tree;
+use crate::process::format::{Format, Formatter};
+use crate::process::identify::{Identifier, Identify};
+use crate::process::pipeline::{unprocess, unprocess_bytes};
+use crate::process::Instanciate;
+use crate::repo::index::{self, IndexRead, MasterIndex};
+use crate::repo::pack::{PackRead, PackReader};
+use crate::repo::snapshot::SnapshotRead;
+use crate::repo::{LockedRepo, RepoError};
+
+pub type Result<T, E = RepoError> = std::result::Result<T, E>;
+
+/// Which packs to read completely.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadData {
+	#[default]
+	None,
+	All,
+	/// Only the `part`th of `of` equally sized (by count) subsets of packs
+	/// (`part` starts at `1`)
+	///
+	/// Checking all parts from `1` to `of` covers all packs.
+	Subset {
+		part: u32,
+		of: u32,
+	},
+}
+
+impl ReadData {
+	pub fn includes(&self, pack: &Id) -> bool {
+		match *self {
+			Self::None => false,
+			Self::All => true,
+			Self::Subset { part, of } => {
+				let bytes = pack.as_bytes();
+				let n = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+				of > 0 && n % of == part.wrapping_sub(1)
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+	/// The backend structure is invalid
+	Structure,
+	InvalidIndex(Id),
+	InvalidSnapshot(Id),
+	/// A pack listed in an index does not exist
+	MissingPack(Id),
+	/// A blob referenced by a snapshot is not listed in any index
+	MissingBlob(Id),
+	InvalidTree {
+		tree: Id,
+		reason: String,
+	},
+	InvalidPack {
+		pack: Id,
+		reason: String,
+	},
+	InvalidBlob {
+		pack: Id,
+		blob: Id,
+		reason: String,
+	},
+}
+
+impl fmt::Display for Problem {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Structure => f.write_str("Invalid repository structure"),
+			Self::InvalidIndex(id) => write!(f, "Index {id} can not be read"),
+			Self::InvalidSnapshot(id) => write!(f, "Snapshot {id} can not be read"),
+			Self::MissingPack(id) => write!(f, "Pack {id} is indexed but does not exist"),
+			Self::MissingBlob(id) => write!(f, "Blob {id} is used but not indexed"),
+			Self::InvalidTree { tree, reason } => write!(f, "Tree {tree} is invalid: {reason}"),
+			Self::InvalidPack { pack, reason } => write!(f, "Pack {pack} is invalid: {reason}"),
+			Self::InvalidBlob { pack, blob, reason } => {
+				write!(f, "Blob {blob} in pack {pack} is invalid: {reason}")
+			}
+		}
+	}
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct CheckReport {
+	pub snapshots: u64,
+	pub trees: u64,
+	pub packs: u64,
+	/// Packs which were read completely
+	pub packs_read: u64,
+	/// Blobs which were read and verified
+	pub blobs_read: u64,
+	/// Packs which are not part of any index (e.g. after an interrupted
+	/// backup); Removed by the next prune
+	pub unindexed_packs: Vec<Id>,
+	pub problems: Vec<Problem>,
+}
+
+impl CheckReport {
+	pub const fn is_ok(&self) -> bool {
+		self.problems.is_empty()
+	}
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Checker {
+	read_data: ReadData,
+}
+
+impl Checker {
+	pub const fn new() -> Self {
+		Self {
+			read_data: ReadData::None,
+		}
+	}
+
+	pub const fn read_data(mut self, read_data: ReadData) -> Self {
+		self.read_data = read_data;
+		self
+	}
+
+	/// Checks the repository and collects all found problems.
+	///
+	/// Only fails if the repository can not be accessed at all.
+	pub fn check<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
+		&self,
+		repo: &LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
+	) -> Result<CheckReport>
+	where
+		B: BackendWrite,
+		INDEX: AccessShared,
+		SNAPSHOT: AccessShared,
+		PACK: AccessShared,
+	{
+		let mut report = CheckReport::default();
+
+		log::debug!("Checking structure");
+		if repo.verify().is_err() {
+			report.problems.push(Problem::Structure);
+		}
+
+		log::debug!("Checking indices");
+		let mut indices = Vec::new();
+		for id in repo.indices()? {
+			let id = id?;
+			match repo.index_read(&id) {
+				Ok(index) => indices.push((id, index)),
+				Err(_) => report.problems.push(Problem::InvalidIndex(id)),
+			}
+		}
+		let (_, packs) = index::resolve(indices);
+
+		let mut master = MasterIndex::default();
+		for pack in &packs {
+			master.insert(pack);
+		}
+
+		log::debug!("Checking packs");
+		let mut existing = HashSet::new();
+		for id in repo.packs()? {
+			existing.insert(id?);
+		}
+
+		for pack in &packs {
+			if !existing.contains(&pack.id) {
+				report.problems.push(Problem::MissingPack(pack.id));
+			}
+		}
+		report.packs = packs.len() as u64;
+		report.unindexed_packs = existing
+			.iter()
+			.filter(|id| !master.contains_pack(id))
+			.copied()
+			.collect();
+
+		log::debug!("Checking snapshots");
+		let mut trees = Vec::new();
+		for id in repo.snapshots()? {
+			let id = id?;
+			match repo.snapshot_read(&id) {
+				Ok(snapshot) => {
+					report.snapshots += 1;
+					trees.push(snapshot.tree);
+				}
+				Err(_) => report.problems.push(Problem::InvalidSnapshot(id)),
+			}
+		}
+		check_trees(&repo.pack_reader(), &master, trees, &mut report);
+
+		if self.read_data != ReadData::None {
+			let identifier = repo.config().process.identifier.create();
+
+			for pack in packs
+				.iter()
+				.filter(|p| existing.contains(&p.id) && self.read_data.includes(&p.id))
+			{
+				log::debug!("Reading pack {}", pack.id);
+				check_pack(repo, repo.key(), &identifier, pack, &mut report);
+			}
+		}
+
+		Ok(report)
+	}
+}
+
+/// Checks that all trees and blobs reachable from `trees` are indexed and
+/// that the trees can be read.
+fn check_trees<R: PackRead>(
+	reader: &PackReader<'_, R>,
+	index: &MasterIndex,
+	trees: Vec<Id>,
+	report: &mut CheckReport,
+) {
+	let mut seen = HashSet::new();
+	let mut pending = trees;
+
+	while let Some(id) = pending.pop() {
+		if !seen.insert(id) {
+			continue;
+		}
+
+		let Some(location) = index.get(&id) else {
+			report.problems.push(Problem::MissingBlob(id));
+			continue;
+		};
+
+		report.trees += 1;
+
+		let tree = reader
+			.read_blob(&location.pack, &location.to_entry(id))
+			.map_err(|_| "Failed to read the tree".to_string())
+			.and_then(|bytes| {
+				Formatter::Cbor
+					.parse::<Tree>(&bytes)
+					.map_err(|err| err.to_string())
+			});
+
+		let tree = match tree {
+			Ok(tree) => tree,
+			Err(reason) => {
+				report
+					.problems
+					.push(Problem::InvalidTree { tree: id, reason });
+				continue;
+			}
+		};
+
+		for node in tree {
+			match node.kind {
+				NodeKind::Directory {
+					subtree: Some(subtree),
+				} => pending.push(subtree),
+				NodeKind::File { blobs } => {
+					for blob in blobs {
+						if !index.contains(&blob) && seen.insert(blob) {
+							report.problems.push(Problem::MissingBlob(blob));
+						}
+					}
+				}
+				_ => {}
+			}
+		}
+	}
+}
+
+/// Reads the complete pack `pack` and verifies it against its index entry.
+fn check_pack<R: PackRead>(
+	repo: &R,
+	key: &Key,
+	identifier: &Identifier,
+	pack: &PackEntry,
+	report: &mut CheckReport,
+) {
+	let id = pack.id;
+	let mut problem = |reason: &str| {
+		report.problems.push(Problem::InvalidPack {
+			pack: id,
+			reason: reason.to_string(),
+		});
+	};
+
+	let bytes = match repo.pack_read(&id) {
+		Ok(bytes) => bytes,
+		Err(err) => {
+			problem(&format!("Can not be read: {err}"));
+			return;
+		}
+	};
+	report.packs_read += 1;
+
+	if !matches!(identifier.identify(key, &bytes), Ok(actual) if actual == id) {
+		problem("Id does not match its content");
+	}
+
+	let Some(raw) = Pack::from_bytes(bytes) else {
+		problem("Too short");
+		return;
+	};
+
+	let Ok(header) = unprocess::<PackHeader>(Formatter::Cbor, key, raw.header()) else {
+		problem("Header can not be read");
+		return;
+	};
+
+	let blobs: Vec<_> = header.blobs().collect();
+	if blobs != pack.blobs {
+		problem("Header does not match the index");
+	}
+
+	for blob in blobs {
+		let start = blob.offset as usize;
+		let end = start + blob.processed_len as usize;
+
+		let reason = match raw.blobs().get(start..end) {
+			None => Some("Out of bounds".to_string()),
+			Some(bytes) => match unprocess_bytes(&Formatter::Cbor, key, bytes) {
+				Err(err) => Some(err.to_string()),
+				Ok(bytes) => match identifier.identify(key, &bytes) {
+					Ok(actual) if actual == blob.id => None,
+					_ => Some("Id does not match its content".to_string()),
+				},
+			},
+		};
+
+		report.blobs_read += 1;
+
+		if let Some(reason) = reason {
+			report.problems.push(Problem::InvalidBlob {
+				pack: id,
+				blob: blob.id,
+				reason,
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn read_data_subsets() {
+		let ids: Vec<Id> = (0..64).map(|_| Id::random()).collect();
+
+		for id in &ids {
+			assert!(ReadData::All.includes(id));
+			assert!(!ReadData::None.includes(id));
+
+			let parts = (1..=3)
+				.filter(|&part| ReadData::Subset { part, of: 3 }.includes(id))
+				.count();
+			assert_eq!(parts, 1);
+		}
+
+		assert!(!ids
+			.iter()
+			.any(|id| ReadData::Subset { part: 1, of: 0 }.includes(id)));
+	}
+}
@@ -0,0 +1,329 @@
+//! Retention policies to decide which snapshots to keep.
+//!
+//! Policies are applied separately to each [`SnapshotGroup`] (host + root).
+//! All time buckets (hour, day, ...) are computed in UTC.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+
+use crate::id::Idd;
+use crate::obj::snapshot::{Snapshot, SnapshotGroup};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeepReason {
+	Last,
+	Hourly,
+	Daily,
+	Weekly,
+	Monthly,
+	Yearly,
+	Within,
+	Tag(String),
+}
+
+impl fmt::Display for KeepReason {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Last => f.write_str("last"),
+			Self::Hourly => f.write_str("hourly"),
+			Self::Daily => f.write_str("daily"),
+			Self::Weekly => f.write_str("weekly"),
+			Self::Monthly => f.write_str("monthly"),
+			Self::Yearly => f.write_str("yearly"),
+			Self::Within => f.write_str("within"),
+			Self::Tag(tag) => write!(f, "tag `{tag}`"),
+		}
+	}
+}
+
+/// Which snapshots to keep.
+///
+/// A count of `n` keeps the newest snapshot of each of the last `n` buckets
+/// (e.g. days) which contain a snapshot.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct RetentionPolicy {
+	pub last: u32,
+	pub hourly: u32,
+	pub daily: u32,
+	pub weekly: u32,
+	pub monthly: u32,
+	pub yearly: u32,
+	/// Keeps all snapshots within this duration of the newest snapshot of the
+	/// group
+	pub within: Option<Duration>,
+	/// Keeps all snapshots which have any of these tags
+	pub tags: Vec<String>,
+}
+
+/// Decision made for a single snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+	pub snapshot: Idd<Snapshot>,
+	/// Why the snapshot is kept; Empty if it should be removed
+	pub reasons: Vec<KeepReason>,
+}
+
+impl Decision {
+	pub const fn keep(&self) -> bool {
+		!self.reasons.is_empty()
+	}
+}
+
+impl RetentionPolicy {
+	/// Returns `true` if the policy would not keep any snapshot.
+	pub const fn is_empty(&self) -> bool {
+		self.last == 0
+			&& self.hourly == 0
+			&& self.daily == 0
+			&& self.weekly == 0
+			&& self.monthly == 0
+			&& self.yearly == 0
+			&& self.within.is_none()
+			&& self.tags.is_empty()
+	}
+
+	/// Groups `snapshots` and decides for each one whether to keep it.
+	///
+	/// The decisions of each group are ordered from newest to oldest.
+	pub fn apply(&self, snapshots: Vec<Idd<Snapshot>>) -> BTreeMap<SnapshotGroup, Vec<Decision>> {
+		let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+		for snapshot in snapshots {
+			groups
+				.entry(snapshot.value.group())
+				.or_default()
+				.push(snapshot);
+		}
+
+		groups
+			.into_iter()
+			.map(|(group, snapshots)| (group, self.apply_group(snapshots)))
+			.collect()
+	}
+
+	fn apply_group(&self, mut snapshots: Vec<Idd<Snapshot>>) -> Vec<Decision> {
+		snapshots.sort_by_key(|s| Reverse(s.value.time));
+
+		let newest = snapshots.first().map(|s| s.value.time);
+
+		let mut buckets = [
+			(KeepReason::Last, self.last, Bucket::Last),
+			(KeepReason::Hourly, self.hourly, Bucket::Hour),
+			(KeepReason::Daily, self.daily, Bucket::Day),
+			(KeepReason::Weekly, self.weekly, Bucket::Week),
+			(KeepReason::Monthly, self.monthly, Bucket::Month),
+			(KeepReason::Yearly, self.yearly, Bucket::Year),
+		]
+		.map(|(reason, count, bucket)| (reason, count, bucket, None));
+
+		let mut decisions = Vec::with_capacity(snapshots.len());
+
+		for (idx, snapshot) in snapshots.into_iter().enumerate() {
+			let time = snapshot.value.time;
+			let mut reasons = Vec::new();
+
+			for (reason, count, bucket, last) in &mut buckets {
+				if *count == 0 {
+					continue;
+				}
+
+				let key = bucket.key(idx, time);
+				if *last != Some(key) {
+					*last = Some(key);
+					*count -= 1;
+					reasons.push(reason.clone());
+				}
+			}
+
+			if let (Some(within), Some(newest)) = (self.within, newest) {
+				if time >= newest - within {
+					reasons.push(KeepReason::Within);
+				}
+			}
+
+			for tag in &self.tags {
+				if snapshot.value.tags.contains(tag) {
+					reasons.push(KeepReason::Tag(tag.clone()));
+				}
+			}
+
+			decisions.push(Decision { snapshot, reasons });
+		}
+
+		decisions
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Bucket {
+	/// Every snapshot is its own bucket
+	Last,
+	Hour,
+	Day,
+	Week,
+	Month,
+	Year,
+}
+
+impl Bucket {
+	fn key(self, idx: usize, time: DateTime<Utc>) -> (i32, u32, u32, u32) {
+		match self {
+			Self::Last => (0, 0, 0, idx.try_into().unwrap_or(u32::MAX)),
+			Self::Hour => (time.year(), time.month(), time.day(), time.hour()),
+			Self::Day => (time.year(), time.month(), time.day(), 0),
+			Self::Week => {
+				let week = time.iso_week();
+				(week.year(), week.week(), 0, 0)
+			}
+			Self::Month => (time.year(), time.month(), 0, 0),
+			Self::Year => (time.year(), 0, 0, 0),
+		}
+	}
+}
+
+/// Counts how many snapshots of all `decisions` are kept and removed.
+pub fn count(decisions: &BTreeMap<SnapshotGroup, Vec<Decision>>) -> (usize, usize) {
+	let (keep, remove): (Vec<_>, Vec<_>) = decisions.values().flatten().partition(|d| d.keep());
+
+	(keep.len(), remove.len())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::id::Id;
+
+	fn snapshot(time: &str, tags: &[&str]) -> Idd<Snapshot> {
+		Id::random().idd(Snapshot {
+			time: time.parse().unwrap(),
+			tags: tags.iter().map(ToString::to_string).collect(),
+			..Default::default()
+		})
+	}
+
+	fn kept(policy: &RetentionPolicy, snapshots: Vec<Idd<Snapshot>>) -> Vec<DateTime<Utc>> {
+		let decisions = policy.apply(snapshots);
+		assert_eq!(decisions.len(), 1);
+
+		decisions
+			.into_values()
+			.flatten()
+			.filter(Decision::keep)
+			.map(|d| d.snapshot.value.time)
+			.collect()
+	}
+
+	fn time(s: &str) -> DateTime<Utc> {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn keep_last() {
+		let policy = RetentionPolicy {
+			last: 2,
+			..Default::default()
+		};
+
+		let kept = kept(
+			&policy,
+			vec![
+				snapshot("2023-01-01T00:00:00Z", &[]),
+				snapshot("2023-01-03T00:00:00Z", &[]),
+				snapshot("2023-01-02T00:00:00Z", &[]),
+			],
+		);
+
+		assert_eq!(
+			kept,
+			[time("2023-01-03T00:00:00Z"), time("2023-01-02T00:00:00Z")]
+		);
+	}
+
+	#[test]
+	fn keep_daily_and_monthly() {
+		let policy = RetentionPolicy {
+			daily: 2,
+			monthly: 2,
+			..Default::default()
+		};
+
+		let kept = kept(
+			&policy,
+			vec![
+				snapshot("2023-02-02T12:00:00Z", &[]),
+				snapshot("2023-02-02T08:00:00Z", &[]),
+				snapshot("2023-02-01T08:00:00Z", &[]),
+				snapshot("2023-01-31T08:00:00Z", &[]),
+				snapshot("2023-01-15T08:00:00Z", &[]),
+				snapshot("2022-12-31T08:00:00Z", &[]),
+			],
+		);
+
+		assert_eq!(
+			kept,
+			[
+				// daily + monthly
+				time("2023-02-02T12:00:00Z"),
+				// daily
+				time("2023-02-01T08:00:00Z"),
+				// monthly
+				time("2023-01-31T08:00:00Z"),
+			]
+		);
+	}
+
+	#[test]
+	fn keep_within_and_tag() {
+		let policy = RetentionPolicy {
+			within: Some(Duration::days(2)),
+			tags: vec!["keep".to_string()],
+			..Default::default()
+		};
+
+		let decisions = policy.apply(vec![
+			snapshot("2023-01-10T00:00:00Z", &[]),
+			snapshot("2023-01-08T00:00:00Z", &[]),
+			snapshot("2023-01-07T00:00:00Z", &[]),
+			snapshot("2023-01-01T00:00:00Z", &["keep"]),
+		]);
+		let decisions = decisions.into_values().flatten().collect::<Vec<_>>();
+
+		assert_eq!(
+			decisions
+				.iter()
+				.map(|d| d.reasons.clone())
+				.collect::<Vec<_>>(),
+			[
+				vec![KeepReason::Within],
+				vec![KeepReason::Within],
+				vec![],
+				vec![KeepReason::Tag("keep".to_string())],
+			]
+		);
+		assert_eq!(count(&policy.apply(Vec::new())), (0, 0));
+	}
+
+	#[test]
+	fn groups_are_separate() {
+		let policy = RetentionPolicy {
+			last: 1,
+			..Default::default()
+		};
+
+		let mut other = snapshot("2023-01-01T00:00:00Z", &[]);
+		other.value.root = std::ffi::OsString::from("/other").into();
+
+		let decisions = policy.apply(vec![
+			snapshot("2023-01-02T00:00:00Z", &[]),
+			snapshot("2023-01-03T00:00:00Z", &[]),
+			other,
+		]);
+
+		assert_eq!(decisions.len(), 2);
+		assert_eq!(count(&decisions), (2, 1));
+		assert!(decisions.values().all(|d| d[0].keep()));
+	}
+}
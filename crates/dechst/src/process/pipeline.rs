@@ -89,15 +89,14 @@ pub fn unprocess<'de, V: serde::de::Deserialize<'de>>(
 	key: &Key,
 	bytes: &[u8],
 ) -> Result<V> {
-	let tagged: TaggedChunk = format.parse(&bytes).unwrap();
+	let bytes = unprocess_bytes(&format, key, bytes)?;
 
-	let bytes = tagged
-		.verify(&key)
-		.unwrap()
-		.decrypt(&key)
-		.unwrap()
-		.decompress()
-		.unwrap();
+	Ok(format.parse(&bytes)?)
+}
+
+/// Reverses [`ChunkPipeline::process`] without parsing the resulting bytes.
+pub fn unprocess_bytes(format: &Formatter, key: &Key, bytes: &[u8]) -> Result<Vec<u8>> {
+	let tagged: TaggedChunk = format.parse(bytes)?;
 
-	Ok(format.parse(&bytes).unwrap())
+	Ok(tagged.verify(key)?.decrypt(key)?.decompress()?)
 }
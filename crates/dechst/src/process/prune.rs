@@ -0,0 +1,446 @@
+//! Removes blobs which are no longer referenced by any snapshot.
+//!
+//! 1. Collect all blobs referenced by the trees of all snapshots
+//! 2. Plan for each indexed pack whether to keep, repack or remove it
+//! 3. Copy the used blobs of packs which are repacked into new packs
+//! 4. Write a new index which supersedes all existing ones
+//! 5. Remove the old indices, followed by all obsolete packs
+//!
+//! Packs are only removed after the new index was written, so an interrupted
+//! prune never loses any used blob. Packs which were written but are not yet
+//! part of an index are cleaned up by a later prune once they are older than
+//! [`Pruner::LEFTOVER_AGE`], as they might still belong to a running backup.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::backend::BackendWrite;
+use crate::id::Id;
+use crate::obj::index::{BlobEntry, Index, PackEntry};
+use crate::obj::lock::sealed::AccessExclusive;
+use crate::obj::tree::node::NodeKind;
+use crate::obj::tree::Tree;
+use crate::process::build::pack::{PackBuilder, PackError};
+use crate::process::format::{Format, Formatter};
+use crate::process::pipeline::PipelineError;
+use crate::process::Instanciate;
+use crate::repo::index::{self, IndexRead, IndexUpdate, MasterIndex};
+use crate::repo::pack::{PackRead, PackReader, PackUpdate};
+use crate::repo::snapshot::SnapshotRead;
+use crate::repo::{LockedRepo, RepoError};
+
+#[derive(Debug)]
+pub enum PruneError {
+	Pipeline(PipelineError),
+	Pack(PackError),
+	Repo(RepoError),
+	MissingBlob(Id),
+}
+
+impl From<PipelineError> for PruneError {
+	fn from(value: PipelineError) -> Self {
+		Self::Pipeline(value)
+	}
+}
+
+impl From<PackError> for PruneError {
+	fn from(value: PackError) -> Self {
+		Self::Pack(value)
+	}
+}
+
+impl fmt::Display for PruneError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Pipeline(inner) => write!(f, "Pipeline: {inner}"),
+			Self::Pack(inner) => write!(f, "Pack: {inner}"),
+			Self::Repo(inner) => write!(f, "Repository: {inner}"),
+			Self::MissingBlob(id) => write!(f, "Blob {id} is missing from the index"),
+		}
+	}
+}
+
+impl std::error::Error for PruneError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Pipeline(s) => Some(s),
+			Self::Pack(s) => Some(s),
+			Self::Repo(s) => Some(s),
+			Self::MissingBlob(_) => None,
+		}
+	}
+}
+
+pub type Result<T, E = PruneError> = std::result::Result<T, E>;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PruneStats {
+	/// Blobs referenced by any snapshot
+	pub used_blobs: u64,
+	/// Blobs which are not referenced or duplicates
+	pub unused_blobs: u64,
+	/// Packs which only contained unused blobs
+	pub removed_packs: u64,
+	/// Packs of which the used blobs were copied into new packs
+	pub repacked_packs: u64,
+	/// Packs which are not part of any index and old enough to be removed
+	pub orphaned_packs: u64,
+	/// Packs which are not part of any index yet, but are too recent to be
+	/// removed
+	pub recent_packs: u64,
+	/// Packs written while repacking (always `0` in a dry run)
+	pub written_packs: u64,
+	/// Size of all removed, repacked and orphaned packs
+	pub removed_bytes: u64,
+	/// Size of the used blobs which were copied into new packs
+	pub repacked_bytes: u64,
+	/// Leftovers of interrupted writes (always `0` in a dry run)
+	pub removed_leftovers: u64,
+}
+
+/// Garbage collects unused blobs of a repository.
+#[derive(Debug, Clone, Copy)]
+pub struct Pruner {
+	max_waste: u8,
+	dry_run: bool,
+}
+
+impl Default for Pruner {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Pruner {
+	pub const DEFAULT_MAX_WASTE: u8 = 10;
+	/// Leftovers of interrupted writes are only removed after this time, so
+	/// that writes which are still in progress are not affected
+	pub const LEFTOVER_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+	pub const fn new() -> Self {
+		Self {
+			max_waste: Self::DEFAULT_MAX_WASTE,
+			dry_run: false,
+		}
+	}
+
+	/// Packs of which more than `percent` of the bytes are unused get
+	/// repacked.
+	pub const fn max_waste(mut self, percent: u8) -> Self {
+		self.max_waste = percent;
+		self
+	}
+
+	/// Only computes the stats without modifying the repository.
+	pub const fn dry_run(mut self, dry_run: bool) -> Self {
+		self.dry_run = dry_run;
+		self
+	}
+
+	pub fn prune<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
+		&self,
+		repo: &mut LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
+	) -> Result<PruneStats>
+	where
+		B: BackendWrite,
+		INDEX: AccessExclusive,
+		SNAPSHOT: AccessExclusive,
+		PACK: AccessExclusive,
+	{
+		// Refuse before anything is written
+		if !self.dry_run && repo.is_append_only() {
+			return Err(PruneError::Repo(RepoError::AppendOnly));
+		}
+
+		log::debug!("Loading index");
+		let mut indices = Vec::new();
+		for id in repo.indices().map_err(PruneError::Repo)? {
+			let id = id.map_err(|err| PruneError::Repo(err.into()))?;
+			indices.push((id, repo.index_read(&id).map_err(PruneError::Repo)?));
+		}
+		let (indices, packs) = index::resolve(indices);
+
+		let mut master = MasterIndex::default();
+		for pack in &packs {
+			master.insert(pack);
+		}
+
+		log::debug!("Finding used blobs");
+		let mut trees = Vec::new();
+		for snapshot in repo.snapshots_read().map_err(PruneError::Repo)? {
+			trees.push(snapshot.value.tree);
+		}
+		let used = used_blobs(&repo.pack_reader(), &master, trees)?;
+
+		log::debug!("Finding orphaned packs");
+		let indexed: HashSet<Id> = packs.iter().map(|p| p.id).collect();
+		let mut orphaned = Vec::new();
+		let mut orphaned_bytes = 0;
+		let mut recent = 0;
+		for id in repo.packs().map_err(PruneError::Repo)? {
+			let id = id.map_err(|err| PruneError::Repo(err.into()))?;
+			if indexed.contains(&id) {
+				continue;
+			}
+
+			let meta = repo.pack_meta(&id).map_err(PruneError::Repo)?;
+			let age = meta
+				.modified
+				.and_then(|modified| Utc::now().signed_duration_since(modified).to_std().ok())
+				.unwrap_or_default();
+
+			if age >= Self::LEFTOVER_AGE {
+				orphaned.push(id);
+				orphaned_bytes += meta.len;
+			} else {
+				log::debug!("Keeping recent pack {id} which is not indexed yet");
+				recent += 1;
+			}
+		}
+
+		let plan = plan(packs, &used, self.max_waste);
+
+		let mut stats = plan.stats;
+		stats.used_blobs = used.len() as u64;
+		stats.orphaned_packs = orphaned.len() as u64;
+		stats.recent_packs = recent;
+		stats.removed_bytes += orphaned_bytes;
+
+		if self.dry_run {
+			return Ok(stats);
+		}
+
+		let obsolete: Vec<Id> = plan
+			.remove
+			.iter()
+			.copied()
+			.chain(plan.repack.iter().map(|(pack, _)| *pack))
+			.collect();
+
+		if !obsolete.is_empty() {
+			let mut packs = plan.keep;
+			let written = repack(repo, &plan.repack)?;
+			stats.written_packs = written.len() as u64;
+			packs.extend(written);
+
+			log::debug!("Writing index for {} pack(s)", packs.len());
+			let index = Index {
+				supersedes: Some(indices.clone()),
+				packs,
+				// Lists all remaining packs, so the removed ones need no
+				// tombstone which could hide them if they are written again
+				delete: Vec::new(),
+			};
+			repo.index_write(&index).map_err(PruneError::Repo)?;
+
+			for id in &indices {
+				repo.index_remove(id).map_err(PruneError::Repo)?;
+			}
+		}
+
+		for id in obsolete.iter().chain(&orphaned) {
+			log::debug!("Removing pack {id}");
+			repo.pack_remove(id).map_err(PruneError::Repo)?;
+		}
+
+		log::debug!("Removing leftovers");
+		stats.removed_leftovers = repo.clean(Self::LEFTOVER_AGE).map_err(PruneError::Repo)?;
+
+		Ok(stats)
+	}
+}
+
+/// Collects the ids of the trees `trees` and of all blobs referenced by them.
+fn used_blobs<R: PackRead>(
+	reader: &PackReader<'_, R>,
+	index: &MasterIndex,
+	trees: Vec<Id>,
+) -> Result<HashSet<Id>> {
+	let mut used = HashSet::new();
+	let mut pending = trees;
+
+	while let Some(id) = pending.pop() {
+		if !used.insert(id) {
+			continue;
+		}
+
+		let location = index.get(&id).ok_or(PruneError::MissingBlob(id))?;
+		let bytes = reader
+			.read_blob(&location.pack, &location.to_entry(id))
+			.map_err(PruneError::Repo)?;
+		let tree: Tree = Formatter::Cbor.parse(&bytes).map_err(PipelineError::from)?;
+
+		for node in tree {
+			match node.kind {
+				NodeKind::Directory {
+					subtree: Some(subtree),
+				} => pending.push(subtree),
+				NodeKind::File { blobs } => used.extend(blobs),
+				_ => {}
+			}
+		}
+	}
+
+	// Never drop a pack if the repository is already missing blobs
+	if let Some(id) = used.iter().find(|id| !index.contains(id)) {
+		return Err(PruneError::MissingBlob(*id));
+	}
+
+	Ok(used)
+}
+
+/// Copies the blobs `repack` into new packs and returns the written packs.
+fn repack<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
+	repo: &mut LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
+	repack: &[(Id, Vec<BlobEntry>)],
+) -> Result<Vec<PackEntry>>
+where
+	B: BackendWrite,
+	PACK: AccessExclusive,
+{
+	let identifier = repo.config().process.identifier;
+	let mut builder = PackBuilder::new(repo.pipeline(), identifier.create());
+	let mut written = Vec::new();
+
+	for (pack, blobs) in repack {
+		log::debug!("Repacking {} blob(s) of pack {pack}", blobs.len());
+
+		for blob in blobs {
+			let bytes = repo
+				.pack_reader()
+				.read_raw(pack, blob.offset, blob.processed_len)
+				.map_err(PruneError::Repo)?;
+
+			builder.add_processed(blob.kind, blob.id, &bytes, blob.unprocessed_len)?;
+
+			if builder.is_full() {
+				let full = std::mem::replace(
+					&mut builder,
+					PackBuilder::new(repo.pipeline(), identifier.create()),
+				);
+				written.push(write_pack(repo, full)?);
+			}
+		}
+	}
+
+	if !builder.is_empty() {
+		written.push(write_pack(repo, builder)?);
+	}
+
+	Ok(written)
+}
+
+fn write_pack<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
+	repo: &mut LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
+	builder: PackBuilder,
+) -> Result<PackEntry>
+where
+	B: BackendWrite,
+	PACK: AccessExclusive,
+{
+	let pack = builder.finish()?;
+
+	log::debug!("Writing pack {} ({} bytes)", pack.id, pack.bytes.len());
+	repo.pack_write(&pack.id, &pack.bytes)
+		.map_err(PruneError::Repo)?;
+
+	Ok(pack.entry)
+}
+
+#[derive(Default, Debug)]
+struct Plan {
+	keep: Vec<PackEntry>,
+	/// Packs to repack together with their used blobs
+	repack: Vec<(Id, Vec<BlobEntry>)>,
+	remove: Vec<Id>,
+	stats: PruneStats,
+}
+
+/// Decides for each pack whether to keep, repack or remove it.
+///
+/// A blob contained in multiple packs is only considered used in the first
+/// one.
+fn plan(packs: Vec<PackEntry>, used: &HashSet<Id>, max_waste: u8) -> Plan {
+	let mut plan = Plan::default();
+	let mut claimed = HashSet::new();
+
+	for pack in packs {
+		let blobs: Vec<BlobEntry> = pack
+			.blobs
+			.iter()
+			.filter(|b| used.contains(&b.id) && claimed.insert(b.id))
+			.copied()
+			.collect();
+
+		let total: u64 = pack.blobs.iter().map(|b| u64::from(b.processed_len)).sum();
+		let live: u64 = blobs.iter().map(|b| u64::from(b.processed_len)).sum();
+		let size = pack.size.map_or(total, |s| u64::from(s.get()));
+
+		plan.stats.unused_blobs += (pack.blobs.len() - blobs.len()) as u64;
+
+		if blobs.is_empty() {
+			plan.stats.removed_packs += 1;
+			plan.stats.removed_bytes += size;
+			plan.remove.push(pack.id);
+		} else if (total - live) * 100 > total * u64::from(max_waste) {
+			plan.stats.repacked_packs += 1;
+			plan.stats.removed_bytes += size;
+			plan.stats.repacked_bytes += live;
+			plan.repack.push((pack.id, blobs));
+		} else {
+			plan.keep.push(pack);
+		}
+	}
+
+	plan
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::obj::index::test::{id, pack};
+
+	#[test]
+	fn plan_packs() {
+		let packs = vec![
+			// All used
+			pack(1, &[10, 11, 12]),
+			// 1/10 unused
+			pack(2, &[20, 21, 22, 23, 24, 25, 26, 27, 28, 29]),
+			// 1/2 unused
+			pack(3, &[30, 31]),
+			// Unused
+			pack(4, &[40]),
+			// Duplicate of a used blob in pack 1
+			pack(5, &[10]),
+		];
+
+		let used: HashSet<Id> = [10, 11, 12, 20, 21, 22, 23, 24, 25, 26, 27, 28, 30]
+			.into_iter()
+			.map(id)
+			.collect();
+
+		let plan = plan(packs, &used, 10);
+
+		assert_eq!(
+			plan.keep.iter().map(|p| p.id).collect::<Vec<_>>(),
+			[id(1), id(2)]
+		);
+		assert_eq!(plan.repack.len(), 1);
+		assert_eq!(plan.repack[0].0, id(3));
+		assert_eq!(
+			plan.repack[0].1.iter().map(|b| b.id).collect::<Vec<_>>(),
+			[id(30)]
+		);
+		assert_eq!(plan.remove, [id(4), id(5)]);
+
+		assert_eq!(plan.stats.unused_blobs, 4);
+		assert_eq!(plan.stats.removed_packs, 2);
+		assert_eq!(plan.stats.repacked_packs, 1);
+		assert_eq!(plan.stats.removed_bytes, 40);
+		assert_eq!(plan.stats.repacked_bytes, 10);
+	}
+}
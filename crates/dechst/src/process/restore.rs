@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Write;
+
+use crate::backend::BackendWrite;
+use crate::id::Id;
+use crate::obj::lock::sealed::AccessShared;
+use crate::obj::snapshot::Snapshot;
+use crate::obj::tree::node::{Node, NodeKind};
+use crate::obj::tree::Tree;
+use crate::path::{PathBuf, Segment};
+use crate::process::format::{Format, Formatter};
+use crate::process::pipeline::PipelineError;
+use crate::repo::index::MasterIndex;
+use crate::repo::pack::{PackRead, PackReader};
+use crate::repo::{LockedRepo, RepoError};
+use crate::target::{RestoreMode, Target};
+
+#[derive(Debug)]
+pub enum RestoreError<E> {
+	Target(E),
+	Write(std::io::Error),
+	Pipeline(PipelineError),
+	Repo(RepoError),
+	MissingBlob(Id),
+	InvalidName(Segment),
+}
+
+impl<E> From<PipelineError> for RestoreError<E> {
+	fn from(value: PipelineError) -> Self {
+		Self::Pipeline(value)
+	}
+}
+
+impl<E: fmt::Display> fmt::Display for RestoreError<E> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Target(inner) => write!(f, "Target: {inner}"),
+			Self::Write(inner) => write!(f, "Write: {inner}"),
+			Self::Pipeline(inner) => write!(f, "Pipeline: {inner}"),
+			Self::Repo(inner) => write!(f, "Repository: {inner}"),
+			Self::MissingBlob(id) => write!(f, "Blob {id} is missing from the index"),
+			Self::InvalidName(name) => write!(f, "Invalid node name `{name}`"),
+		}
+	}
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RestoreError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Target(s) => Some(s),
+			Self::Write(s) => Some(s),
+			Self::Pipeline(s) => Some(s),
+			Self::Repo(s) => Some(s),
+			Self::MissingBlob(_) | Self::InvalidName(_) => None,
+		}
+	}
+}
+
+pub type Result<T, E> = std::result::Result<T, RestoreError<E>>;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreStats {
+	/// Nodes which were created or updated
+	pub restored: u64,
+	/// Nodes which were left untouched because of the [`RestoreMode`]
+	pub skipped: u64,
+	/// Nodes which were not part of the snapshot and got removed
+	pub removed: u64,
+	/// Nodes which failed to be restored
+	pub failed: u64,
+}
+
+/// Restores the [`Tree`] of a [`Snapshot`] into a [`Target`].
+///
+/// Snapshot -> Tree(s) -> Node(s) -> Target
+///
+/// Failing nodes are logged, counted and skipped, so that as much as possible
+/// gets restored.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Restorer {
+	mode: RestoreMode,
+}
+
+impl Restorer {
+	pub const fn new(mode: RestoreMode) -> Self {
+		Self { mode }
+	}
+
+	pub fn restore<T, B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
+		&self,
+		repo: &LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
+		snapshot: &Snapshot,
+		target: &T,
+	) -> Result<RestoreStats, T::Error>
+	where
+		T: Target,
+		B: BackendWrite,
+		INDEX: AccessShared,
+		PACK: AccessShared,
+	{
+		let index = MasterIndex::load(repo).map_err(RestoreError::Repo)?;
+
+		target.create_root().map_err(RestoreError::Target)?;
+
+		let mut restore = Restore {
+			mode: self.mode,
+			index,
+			reader: repo.pack_reader(),
+			target,
+			stats: RestoreStats::default(),
+		};
+
+		restore.restore_tree(None, &snapshot.tree, &mut PathBuf::new())?;
+
+		Ok(restore.stats)
+	}
+}
+
+struct Restore<'a, R, T> {
+	mode: RestoreMode,
+	index: MasterIndex,
+	reader: PackReader<'a, R>,
+	target: &'a T,
+	stats: RestoreStats,
+}
+
+impl<'a, R, T> Restore<'a, R, T>
+where
+	R: PackRead,
+	T: Target,
+{
+	fn restore_tree(
+		&mut self,
+		parent: Option<&T::Item>,
+		id: &Id,
+		path: &mut PathBuf,
+	) -> Result<(), T::Error> {
+		let tree = self.load_tree(id)?;
+		let mut names = HashSet::new();
+
+		for node in tree {
+			names.insert(node.name.clone());
+			path.push(node.name.clone());
+
+			if let Err(err) = self.restore_node(parent, &node, path) {
+				log::warn!("Failed to restore `{path}`: {err}");
+				self.stats.failed += 1;
+			}
+
+			let _ = path.pop();
+		}
+
+		if self.mode.removes() {
+			self.remove_unknown(parent, &names, path)?;
+		}
+
+		Ok(())
+	}
+
+	fn restore_node(
+		&mut self,
+		parent: Option<&T::Item>,
+		node: &Node,
+		path: &mut PathBuf,
+	) -> Result<(), T::Error> {
+		if !is_valid_name(&node.name) {
+			return Err(RestoreError::InvalidName(node.name.clone()));
+		}
+
+		let item = self.target.item(parent, &node.name);
+		let existing = self.target.node(&item).map_err(RestoreError::Target)?;
+
+		let is_dir = matches!(node.kind, NodeKind::Directory { .. });
+
+		let update = match &existing {
+			None if self.mode.creates() => true,
+			Some(existing) if self.mode.updates() => {
+				// Replace nodes of a different kind; files are truncated
+				// and directories reused.
+				let same_kind =
+					std::mem::discriminant(&existing.kind) == std::mem::discriminant(&node.kind);
+
+				if !same_kind || !(is_dir || matches!(node.kind, NodeKind::File { .. })) {
+					self.target.remove(&item).map_err(RestoreError::Target)?;
+				}
+
+				true
+			}
+			// Existing directories are still descended as they might contain
+			// nodes which need to be restored.
+			Some(existing) if is_dir && matches!(existing.kind, NodeKind::Directory { .. }) => {
+				false
+			}
+			_ => {
+				log::debug!("Skipping `{path}`");
+				self.stats.skipped += 1;
+				return Ok(());
+			}
+		};
+
+		match &node.kind {
+			NodeKind::Directory { subtree } => {
+				if update {
+					self.target
+						.create(&item, node)
+						.map_err(RestoreError::Target)?;
+				}
+
+				if let Some(subtree) = subtree {
+					self.restore_tree(Some(&item), subtree, path)?;
+				}
+			}
+			NodeKind::File { blobs } => {
+				let mut write = self.target.write(&item).map_err(RestoreError::Target)?;
+
+				for id in blobs {
+					let bytes = self.load_blob(id)?;
+					write.write_all(&bytes).map_err(RestoreError::Write)?;
+				}
+
+				write.flush().map_err(RestoreError::Write)?;
+			}
+			NodeKind::Socket => {
+				log::info!("Skipping socket `{path}`");
+				self.stats.skipped += 1;
+				return Ok(());
+			}
+			_ => {
+				self.target
+					.create(&item, node)
+					.map_err(RestoreError::Target)?;
+			}
+		}
+
+		if update {
+			self.target
+				.finish(&item, node)
+				.map_err(RestoreError::Target)?;
+			self.stats.restored += 1;
+		}
+
+		Ok(())
+	}
+
+	/// Removes all items within `parent` which are not included in `names`.
+	fn remove_unknown(
+		&mut self,
+		parent: Option<&T::Item>,
+		names: &HashSet<Segment>,
+		path: &mut PathBuf,
+	) -> Result<(), T::Error> {
+		for item in self.target.iter(parent).map_err(RestoreError::Target)? {
+			let item = item.map_err(RestoreError::Target)?;
+
+			let Some(node) = self.target.node(&item).map_err(RestoreError::Target)? else {
+				continue;
+			};
+
+			if names.contains(&node.name) {
+				continue;
+			}
+
+			path.push(node.name);
+			log::debug!("Removing `{path}`");
+
+			match self.target.remove(&item) {
+				Ok(_) => self.stats.removed += 1,
+				Err(err) => {
+					log::warn!("Failed to remove `{path}`: {err}");
+					self.stats.failed += 1;
+				}
+			}
+
+			let _ = path.pop();
+		}
+
+		Ok(())
+	}
+
+	fn load_tree(&self, id: &Id) -> Result<Tree, T::Error> {
+		let bytes = self.load_blob(id)?;
+
+		Ok(Formatter::Cbor.parse(&bytes).map_err(PipelineError::from)?)
+	}
+
+	fn load_blob(&self, id: &Id) -> Result<Vec<u8>, T::Error> {
+		let location = self.index.get(id).ok_or(RestoreError::MissingBlob(*id))?;
+
+		self.reader
+			.read_blob(&location.pack, &location.to_entry(*id))
+			.map_err(RestoreError::Repo)
+	}
+}
+
+/// Guards against nodes escaping their parent directory.
+fn is_valid_name(name: &Segment) -> bool {
+	let name = name.as_raw_bytes();
+
+	!(name.is_empty()
+		|| name == b"."
+		|| name == b".."
+		|| name.contains(&b'/')
+		|| (cfg!(target_family = "windows") && name.contains(&b'\\')))
+}
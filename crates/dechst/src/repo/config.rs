@@ -6,7 +6,7 @@ use crate::obj::lock::sealed::{AccessExclusive, AccessShared};
 use crate::obj::ObjectKind;
 use crate::process::format::Formatter;
 use crate::process::pipeline::unprocess;
-use crate::repo::{LockedRepo, Result};
+use crate::repo::{LockedRepo, RepoError, Result};
 
 const OBJ: ObjectKind = ObjectKind::Config;
 
@@ -21,13 +21,14 @@ where
 	CONFIG: AccessShared,
 {
 	fn config_exists(&self) -> Result<()> {
-		self.backend.exists(OBJ, &Id::ZERO)
+		Ok(self.backend.exists(OBJ, &Id::ZERO)?)
 	}
 
 	fn config_read(&self) -> Result<Config> {
-		let bytes = self.backend.read_to_end(OBJ, &Id::ZERO).unwrap();
+		let bytes = self.backend.read_to_end(OBJ, &Id::ZERO)?;
 
-		Ok(unprocess(Formatter::Cbor, &self.key, &bytes).unwrap())
+		unprocess(Formatter::Cbor, &self.key, &bytes)
+			.map_err(|err| RepoError::unprocess(OBJ, Id::ZERO, err))
 	}
 }
 
@@ -1,21 +1,24 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::backend::ext::{Find, FindIdExt, ReadToEnd};
-use crate::backend::BackendWrite;
+use crate::backend::{self, BackendWrite};
 use crate::id::Id;
-use crate::obj::key::Key;
-use crate::obj::lock::sealed::AccessShared;
+use crate::obj::blob::BlobKind;
+use crate::obj::index::{BlobEntry, Index, PackEntry};
+use crate::obj::lock::sealed::{AccessExclusive, AccessShared};
 use crate::obj::ObjectKind;
 use crate::process::format::Formatter;
 use crate::process::pipeline::unprocess;
-use crate::repo::{LockedRepo, Result};
+use crate::repo::{LockedRepo, RepoError, Result};
 
 const OBJ: ObjectKind = ObjectKind::Index;
 
 pub trait IndexRead {
-	type Iter: Iterator<Item = Result<Id>>;
+	type Iter: Iterator<Item = backend::Result<Id>>;
 
 	fn index_exists(&self, id: &Id) -> Result<()>;
 	fn indices(&self) -> Result<Self::Iter>;
-	fn index_read(&self, id: &Id) -> Result<Key>;
+	fn index_read(&self, id: &Id) -> Result<Index>;
 	fn indices_find(&self, ids: &[&str]) -> Result<Vec<Find>>;
 	fn index_find(&self, id: &str) -> Result<Option<Find>>;
 }
@@ -28,28 +31,332 @@ where
 	type Iter = B::Iter;
 
 	fn index_exists(&self, id: &Id) -> Result<()> {
-		self.backend.exists(OBJ, id)
+		Ok(self.backend.exists(OBJ, id)?)
 	}
 
 	fn indices(&self) -> Result<B::Iter> {
-		self.backend.iter(OBJ)
+		Ok(self.backend.iter(OBJ)?)
 	}
 
-	fn index_read(&self, id: &Id) -> Result<Key> {
-		let bytes = self.backend.read_to_end(OBJ, id).unwrap();
+	fn index_read(&self, id: &Id) -> Result<Index> {
+		let bytes = self.backend.read_to_end(OBJ, id)?;
 
-		let lock = unprocess(Formatter::Cbor, &self.key, &bytes).unwrap();
+		let index = unprocess(Formatter::Cbor, &self.key, &bytes)
+			.map_err(|err| RepoError::unprocess(OBJ, *id, err))?;
 
-		Ok(lock)
+		Ok(index)
 	}
 
 	fn indices_find(&self, ids: &[&str]) -> Result<Vec<Find>> {
-		self.backend.find_ids(OBJ, ids)
+		Ok(self.backend.find_ids(OBJ, ids)?)
 	}
 
 	fn index_find(&self, id: &str) -> Result<Option<Find>> {
-		self.backend.find_id(OBJ, id)
+		Ok(self.backend.find_id(OBJ, id)?)
+	}
+}
+
+pub trait IndexUpdate {
+	fn index_write(&mut self, index: &Index) -> Result<Id>;
+	fn index_remove(&mut self, id: &Id) -> Result<()>;
+
+	/// Compacts the indices `ids` into a single new index which supersedes
+	/// them.
+	///
+	/// The superseded indices are only removed after the new index was
+	/// written, so that an interruption never loses any index entries.
+	fn index_compact(&mut self, ids: &[Id]) -> Result<Id>;
+}
+
+impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK> IndexUpdate
+	for LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+where
+	INDEX: AccessExclusive,
+{
+	fn index_write(&mut self, index: &Index) -> Result<Id> {
+		self.write_object(index)
+	}
+
+	fn index_remove(&mut self, id: &Id) -> Result<()> {
+		self.remove_object(OBJ, id)
+	}
+
+	fn index_compact(&mut self, ids: &[Id]) -> Result<Id> {
+		let mut indices = Vec::with_capacity(ids.len());
+
+		for id in ids {
+			indices.push(self.index_read(id)?);
+		}
+
+		// Deletions are only obsolete if no other index could still reference
+		// a deleted pack.
+		let mut complete = true;
+		for id in self.indices()? {
+			if !ids.contains(&id?) {
+				complete = false;
+				break;
+			}
+		}
+
+		let index = compact(ids, indices, complete);
+		let id = self.index_write(&index)?;
+
+		for old in ids {
+			self.index_remove(old)?;
+		}
+
+		Ok(id)
 	}
 }
 
-pub trait IndexUpdate {}
+/// Merges `indices` into a single index which supersedes `ids`.
+///
+/// Packs listed in `delete` are dropped. If `complete` is set (all indices of
+/// the repository are merged), the deletions themselves are dropped too.
+fn compact(ids: &[Id], indices: Vec<Index>, complete: bool) -> Index {
+	let mut deleted = HashSet::new();
+	let delete: Vec<Id> = indices
+		.iter()
+		.flat_map(|index| index.delete.iter().copied())
+		.filter(|id| deleted.insert(*id))
+		.collect();
+
+	let mut seen = HashSet::new();
+	let packs = indices
+		.into_iter()
+		.flat_map(|index| index.packs)
+		.filter(|pack| !deleted.contains(&pack.id) && seen.insert(pack.id))
+		.collect();
+
+	Index {
+		supersedes: Some(ids.to_vec()),
+		packs,
+		delete: if complete { Vec::new() } else { delete },
+	}
+}
+
+/// Drops all superseded indices and deleted packs (see [`MasterIndex`]).
+///
+/// Returns the ids of the remaining indices and all of their packs.
+pub fn resolve<I: IntoIterator<Item = (Id, Index)>>(indices: I) -> (Vec<Id>, Vec<PackEntry>) {
+	let indices: Vec<_> = indices.into_iter().collect();
+
+	let superseded: HashSet<Id> = indices
+		.iter()
+		.flat_map(|(_, index)| index.supersedes.iter().flatten().copied())
+		.collect();
+
+	let indices: Vec<_> = indices
+		.into_iter()
+		.filter(|(id, _)| !superseded.contains(id))
+		.collect();
+
+	let deleted: HashSet<Id> = indices
+		.iter()
+		.flat_map(|(_, index)| index.delete.iter().copied())
+		.collect();
+
+	let mut seen = HashSet::new();
+	let mut ids = Vec::with_capacity(indices.len());
+	let mut packs = Vec::new();
+
+	for (id, index) in indices {
+		ids.push(id);
+		packs.extend(
+			index
+				.packs
+				.into_iter()
+				.filter(|p| !deleted.contains(&p.id) && seen.insert(p.id)),
+		);
+	}
+
+	(ids, packs)
+}
+
+/// Location of a blob within a pack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobLocation {
+	pub pack: Id,
+	pub kind: BlobKind,
+	pub offset: u32,
+	pub processed_len: u32,
+	pub unprocessed_len: u32,
+}
+
+impl BlobLocation {
+	pub const fn new(pack: Id, entry: &BlobEntry) -> Self {
+		Self {
+			pack,
+			kind: entry.kind,
+			offset: entry.offset,
+			processed_len: entry.processed_len,
+			unprocessed_len: entry.unprocessed_len,
+		}
+	}
+
+	pub const fn to_entry(&self, id: Id) -> BlobEntry {
+		BlobEntry {
+			id,
+			kind: self.kind,
+			offset: self.offset,
+			processed_len: self.processed_len,
+			unprocessed_len: self.unprocessed_len,
+		}
+	}
+}
+
+/// Combined view of all [`Index`] objects of a repository.
+///
+/// - Indices listed in `supersedes` of another index are ignored (they might
+///   still exist if the removal after a compaction was interrupted)
+/// - Packs listed in `delete` of any index are ignored
+#[derive(Default, Debug, Clone)]
+pub struct MasterIndex {
+	indices: Vec<Id>,
+	packs: HashSet<Id>,
+	blobs: HashMap<Id, BlobLocation>,
+}
+
+impl MasterIndex {
+	/// Reads all indices of the repository.
+	pub fn load<R: IndexRead>(repo: &R) -> Result<Self> {
+		let mut indices = Vec::new();
+
+		for id in repo.indices()? {
+			let id = id?;
+			indices.push((id, repo.index_read(&id)?));
+		}
+
+		Ok(Self::from_indices(indices))
+	}
+
+	pub fn from_indices<I: IntoIterator<Item = (Id, Index)>>(indices: I) -> Self {
+		let (indices, packs) = resolve(indices);
+
+		let mut master = Self {
+			indices,
+			..Default::default()
+		};
+
+		for pack in &packs {
+			master.insert(pack);
+		}
+
+		master
+	}
+
+	/// Adds all blobs of `pack` (e.g. after it was written).
+	///
+	/// Blobs which are already known keep their existing location.
+	pub fn insert(&mut self, pack: &PackEntry) {
+		self.packs.insert(pack.id);
+
+		for blob in &pack.blobs {
+			self.blobs
+				.entry(blob.id)
+				.or_insert_with(|| BlobLocation::new(pack.id, blob));
+		}
+	}
+
+	pub fn get(&self, id: &Id) -> Option<&BlobLocation> {
+		self.blobs.get(id)
+	}
+
+	pub fn contains(&self, id: &Id) -> bool {
+		self.blobs.contains_key(id)
+	}
+
+	pub fn contains_pack(&self, id: &Id) -> bool {
+		self.packs.contains(id)
+	}
+
+	/// Ids of all indices which are not superseded.
+	pub fn indices(&self) -> &[Id] {
+		&self.indices
+	}
+
+	pub fn packs(&self) -> impl Iterator<Item = &Id> {
+		self.packs.iter()
+	}
+
+	pub fn blobs(&self) -> impl Iterator<Item = (&Id, &BlobLocation)> {
+		self.blobs.iter()
+	}
+
+	pub fn len(&self) -> usize {
+		self.blobs.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.blobs.is_empty()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::obj::index::test::{id, pack};
+
+	#[test]
+	fn master_index() {
+		let old = Index {
+			packs: vec![pack(1, &[10, 11]), pack(2, &[12])],
+			..Default::default()
+		};
+
+		// Compacted `old` but the removal of `old` was interrupted
+		let compacted = Index {
+			supersedes: Some(vec![id(100)]),
+			packs: vec![pack(1, &[10, 11])],
+			..Default::default()
+		};
+
+		let pruned = Index {
+			packs: vec![pack(3, &[13, 14]), pack(4, &[15])],
+			delete: vec![id(4)],
+			..Default::default()
+		};
+
+		let master =
+			MasterIndex::from_indices([(id(100), old), (id(101), compacted), (id(102), pruned)]);
+
+		assert_eq!(master.indices(), &[id(101), id(102)]);
+
+		assert_eq!(master.len(), 4);
+		assert!(!master.contains(&id(12)));
+		assert!(!master.contains(&id(15)));
+		assert!(!master.contains_pack(&id(4)));
+
+		let loc = master.get(&id(11)).unwrap();
+		assert_eq!(loc.pack, id(1));
+		assert_eq!(loc.offset, 10);
+		assert_eq!(loc.kind, BlobKind::Data);
+		assert_eq!(master.get(&id(14)).unwrap().pack, id(3));
+	}
+
+	#[test]
+	fn compact_indices() {
+		let a = Index {
+			packs: vec![pack(1, &[10]), pack(2, &[11])],
+			..Default::default()
+		};
+
+		let b = Index {
+			packs: vec![pack(2, &[11]), pack(3, &[12])],
+			delete: vec![id(3)],
+			..Default::default()
+		};
+
+		let partial = compact(&[id(100), id(101)], vec![a.clone(), b.clone()], false);
+		assert_eq!(partial.supersedes, Some(vec![id(100), id(101)]));
+		assert_eq!(
+			partial.packs.iter().map(|p| p.id).collect::<Vec<_>>(),
+			vec![id(1), id(2)]
+		);
+		assert_eq!(partial.delete, vec![id(3)]);
+
+		let complete = compact(&[id(100), id(101)], vec![a, b], true);
+		assert_eq!(complete.packs, partial.packs);
+		assert!(complete.delete.is_empty());
+	}
+}
@@ -1,16 +1,16 @@
 use crate::backend::ext::{Find, FindIdExt, ReadToEnd};
-use crate::backend::BackendWrite;
+use crate::backend::{self, BackendWrite};
 use crate::id::Id;
 use crate::obj::key::{EncryptedKey, Key};
 use crate::obj::lock::sealed::AccessShared;
 use crate::obj::ObjectKind;
 use crate::process::format::{Format, Formatter};
-use crate::repo::{LockedRepo, Result};
+use crate::repo::{LockedRepo, RepoError, Result};
 
 const OBJ: ObjectKind = ObjectKind::Key;
 
 pub trait KeyRead {
-	type Iter: Iterator<Item = Result<Id>>;
+	type Iter: Iterator<Item = backend::Result<Id>>;
 
 	fn key_exists(&self, id: &Id) -> Result<()>;
 	fn keys(&self) -> Result<Self::Iter>;
@@ -27,27 +27,31 @@ where
 	type Iter = B::Iter;
 
 	fn key_exists(&self, id: &Id) -> Result<()> {
-		self.backend.exists(OBJ, id)
+		Ok(self.backend.exists(OBJ, id)?)
 	}
 
 	fn keys(&self) -> Result<B::Iter> {
-		self.backend.iter(OBJ)
+		Ok(self.backend.iter(OBJ)?)
 	}
 
 	fn key_read(&self, id: &Id, user_key: &[u8]) -> Result<Key> {
-		let bytes = self.backend.read_to_end(OBJ, id).unwrap();
+		let bytes = self.backend.read_to_end(OBJ, id)?;
 
-		let enc_key: EncryptedKey = Formatter::Cbor.parse(&bytes).unwrap();
+		let enc_key: EncryptedKey = Formatter::Cbor
+			.parse(&bytes)
+			.map_err(|err| RepoError::corrupt(OBJ, *id, err))?;
 
-		Ok(enc_key.decrypt(user_key))
+		enc_key
+			.decrypt(user_key)
+			.map_err(|err| RepoError::key(*id, err))
 	}
 
 	fn keys_find(&self, ids: &[&str]) -> Result<Vec<Find>> {
-		self.backend.find_ids(OBJ, ids)
+		Ok(self.backend.find_ids(OBJ, ids)?)
 	}
 
 	fn key_find(&self, id: &str) -> Result<Option<Find>> {
-		self.backend.find_id(OBJ, id)
+		Ok(self.backend.find_id(OBJ, id)?)
 	}
 }
 
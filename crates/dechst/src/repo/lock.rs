@@ -1,17 +1,28 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::Utc;
+
 use super::DecryptedRepo;
 use crate::backend::ext::{Find, FindIdExt, ReadToEnd};
-use crate::backend::BackendWrite;
+use crate::backend::{self, BackendRead, BackendWrite};
 use crate::id::Id;
-use crate::obj::lock::Lock;
+use crate::obj::config::Config;
+use crate::obj::key::Key;
+use crate::obj::lock::{Lock, LockState};
 use crate::obj::ObjectKind;
-use crate::process::format::Formatter;
-use crate::process::pipeline::unprocess;
-use crate::repo::Result;
+use crate::process::format::{Format, Formatter};
+use crate::process::identify::Identify;
+use crate::process::pipeline::{unprocess, ChunkPipeline};
+use crate::process::Instanciate;
+use crate::repo::{RepoError, Result};
 
 const OBJ: ObjectKind = ObjectKind::Lock;
 
 pub trait LockRead {
-	type Iter: Iterator<Item = Result<Id>>;
+	type Iter: Iterator<Item = backend::Result<Id>>;
 
 	fn lock_exists(&self, id: &Id) -> Result<()>;
 	fn locks(&self) -> Result<Self::Iter>;
@@ -24,28 +35,226 @@ impl<B: BackendWrite> LockRead for DecryptedRepo<B> {
 	type Iter = B::Iter;
 
 	fn lock_exists(&self, id: &Id) -> Result<()> {
-		self.backend.exists(OBJ, id)
+		Ok(self.backend.exists(OBJ, id)?)
 	}
 
 	fn locks(&self) -> Result<B::Iter> {
-		self.backend.iter(OBJ)
+		Ok(self.backend.iter(OBJ)?)
 	}
 
 	fn lock_read(&self, id: &Id) -> Result<Lock> {
-		let bytes = self.backend.read_to_end(OBJ, id).unwrap();
-
-		let lock = unprocess(Formatter::Cbor, &self.key, &bytes).unwrap();
-
-		Ok(lock)
+		read_lock(&self.backend, &self.key, id)
 	}
 
 	fn locks_find(&self, ids: &[&str]) -> Result<Vec<Find>> {
-		self.backend.find_ids(OBJ, ids)
+		Ok(self.backend.find_ids(OBJ, ids)?)
 	}
 
 	fn lock_find(&self, id: &str) -> Result<Option<Find>> {
-		self.backend.find_id(OBJ, id)
+		Ok(self.backend.find_id(OBJ, id)?)
+	}
+}
+
+pub trait LockUpdate {
+	fn lock_remove(&mut self, id: &Id) -> Result<()>;
+}
+
+/// Lock removal is not guarded by a lock itself as it is used to recover from
+/// abandoned locks.
+impl<B: BackendWrite> LockUpdate for DecryptedRepo<B> {
+	fn lock_remove(&mut self, id: &Id) -> Result<()> {
+		Ok(self.backend.remove(OBJ, id)?)
+	}
+}
+
+fn read_lock<B: BackendRead>(backend: &B, key: &Key, id: &Id) -> Result<Lock> {
+	let bytes = backend.read_to_end(OBJ, id)?;
+
+	unprocess(Formatter::Cbor, key, &bytes).map_err(|err| RepoError::unprocess(OBJ, *id, err))
+}
+
+/// Fails if any lock except those in `own` conflicts with `state`.
+///
+/// Stale locks are ignored (see [`crate::obj::lock::LockMeta::is_stale`]).
+pub(crate) fn check_locks<B: BackendRead>(
+	backend: &B,
+	key: &Key,
+	state: &LockState,
+	own: &[Id],
+) -> Result<()> {
+	for id in backend.iter(OBJ)? {
+		let id = id?;
+
+		if own.contains(&id) {
+			continue;
+		}
+
+		let lock = match read_lock(backend, key, &id) {
+			Ok(lock) => lock,
+			// Removed since listing it
+			Err(RepoError::Backend(err)) if err.is_not_found() => continue,
+			Err(err) => return Err(err),
+		};
+
+		if lock.meta.is_stale() {
+			log::warn!("Ignoring stale lock {id:x} of {}", lock.meta);
+			continue;
+		}
+
+		if lock.state.conflicts(state) {
+			return Err(RepoError::Locked {
+				id,
+				meta: Box::new(lock.meta),
+			});
+		}
+	}
+
+	Ok(())
+}
+
+/// Writes `lock` as a new object and returns its id.
+pub(crate) fn write_lock<B: BackendWrite>(
+	backend: &mut B,
+	key: &Key,
+	config: &Config,
+	lock: &Lock,
+) -> Result<Id> {
+	let identifier = config.process.identifier.create();
+	let pipeline = ChunkPipeline::new(config.process, key.clone());
+
+	let bytes = Formatter::Cbor.format(lock)?;
+	let id = identifier.identify(key, &bytes)?;
+
+	let bytes = pipeline.process(&bytes)?;
+
+	backend.write_all(OBJ, &id, &bytes)?;
+
+	Ok(id)
+}
+
+/// Keeps a lock from becoming stale by rewriting it every `interval` in a
+/// background thread.
+///
+/// Each refresh writes the lock with an updated
+/// [`crate::obj::lock::LockMeta::refreshed`] timestamp under a new id and
+/// then removes the previous one.
+#[derive(Debug)]
+pub(crate) struct Heartbeat {
+	lock_id: Arc<Mutex<Id>>,
+	stop: Option<Sender<()>>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+	pub(crate) fn start<B: BackendWrite>(
+		mut backend: B,
+		key: Key,
+		config: Config,
+		mut lock: Lock,
+		lock_id: Id,
+		interval: Duration,
+	) -> Self {
+		let lock_id = Arc::new(Mutex::new(lock_id));
+		let (stop, stopped) = mpsc::channel::<()>();
+
+		let current = Arc::clone(&lock_id);
+		let thread = thread::spawn(move || {
+			// Any message or a disconnect stops the refresh
+			while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+				lock.meta.refreshed = Some(Utc::now());
+
+				let old = *lock_guard(&current);
+
+				let new = match write_lock(&mut backend, &key, &config, &lock) {
+					Ok(new) => new,
+					Err(err) => {
+						log::warn!("Failed to refresh lock {old:x}: {err}");
+						continue;
+					}
+				};
+
+				*lock_guard(&current) = new;
+				log::debug!("Refreshed lock {old:x} as {new:x}");
+
+				if let Err(err) = backend.remove(OBJ, &old) {
+					log::warn!("Failed to remove refreshed lock {old:x}: {err}");
+				}
+			}
+		});
+
+		Self {
+			lock_id,
+			stop: Some(stop),
+			thread: Some(thread),
+		}
+	}
+
+	/// Returns the id of the current lock.
+	pub(crate) fn lock_id(&self) -> Id {
+		*lock_guard(&self.lock_id)
+	}
+
+	/// Stops refreshing and returns the id of the current lock.
+	///
+	/// Waits for a running refresh to finish.
+	pub(crate) fn stop(&mut self) -> Id {
+		drop(self.stop.take());
+
+		if let Some(thread) = self.thread.take() {
+			if thread.join().is_err() {
+				log::error!("Lock refresh thread panicked");
+			}
+		}
+
+		self.lock_id()
+	}
+}
+
+impl Drop for Heartbeat {
+	fn drop(&mut self) {
+		self.stop();
 	}
 }
 
-pub trait LockUpdate {}
+fn lock_guard(id: &Mutex<Id>) -> MutexGuard<'_, Id> {
+	// An `Id` can not be left in an inconsistent state
+	id.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::obj::lock::LockMeta;
+	use crate::repo::marker::LockMarker;
+
+	#[test]
+	fn heartbeat() {
+		let repo = crate::repo::test::repo();
+		let mut backend = repo.backend.clone();
+		let config = repo.config().unwrap();
+
+		let lock = Lock {
+			meta: LockMeta::new(),
+			state: LockState::from(LockMarker::READ),
+		};
+		let lock_id = write_lock(&mut backend, &repo.key, &config, &lock).unwrap();
+
+		let mut heartbeat = Heartbeat::start(
+			backend.clone(),
+			repo.key.clone(),
+			config,
+			lock,
+			lock_id,
+			Duration::from_millis(10),
+		);
+
+		thread::sleep(Duration::from_millis(100));
+		let refreshed = heartbeat.stop();
+
+		assert_ne!(refreshed, lock_id);
+		assert_eq!(backend.len(OBJ), 1);
+
+		let lock = repo.lock_read(&refreshed).unwrap();
+		assert!(lock.meta.refreshed.is_some());
+	}
+}
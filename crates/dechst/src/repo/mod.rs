@@ -2,22 +2,156 @@ pub mod config;
 pub mod index;
 pub mod key;
 pub mod lock;
+pub mod pack;
+pub mod snapshot;
 
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::backend::cache::Cache;
 use crate::backend::ext::{Find, FindIdExt, ReadToEnd};
-use crate::backend::BackendWrite;
+use crate::backend::{self, BackendError, BackendWrite};
 use crate::id::Id;
 use crate::obj::config::Config;
-use crate::obj::key::{EncryptedKey, Key};
-use crate::obj::lock::{Lock, LockMeta, LockState};
-use crate::obj::ObjectKind;
-use crate::process::format::{Format, Formatter};
-use crate::process::identify::Identify;
-use crate::process::pipeline::{unprocess, ChunkPipeline};
+use crate::obj::key::{EncryptedKey, Key, KeyError};
+use crate::obj::lock::sealed::AccessExclusive;
+use crate::obj::lock::{Lock, LockMeta, LockState, Shared};
+use crate::obj::{ObjectKind, RepoObject};
+use crate::process::compress::CompressError;
+use crate::process::encrypt::EncryptError;
+use crate::process::format::{Format, FormatError, Formatter};
+use crate::process::identify::{IdentifyError, Identify};
+use crate::process::pipeline::{unprocess, ChunkPipeline, PipelineError};
+use crate::process::verify::VerifyError;
 use crate::process::Instanciate;
+use crate::repo::lock::Heartbeat;
 use crate::repo::marker::LockMarker;
 
-pub type Error = ();
-pub type Result<T, E = Error> = std::result::Result<T, E>;
+#[derive(Debug)]
+pub enum RepoError {
+	/// The password does not decrypt the key
+	WrongPassword,
+	/// The repository is locked by the lock `id` which conflicts with the
+	/// requested access
+	Locked {
+		id: Id,
+		meta: Box<LockMeta>,
+	},
+	/// The object can not be read
+	Corrupt {
+		kind: ObjectKind,
+		id: Id,
+		reason: String,
+	},
+	/// Objects can not be removed as the repository was opened append-only
+	AppendOnly,
+	/// The repository uses a feature which is not enabled
+	Unsupported(String),
+	/// An object can not be processed before writing it
+	Process(PipelineError),
+	Backend(BackendError),
+}
+
+impl RepoError {
+	pub fn corrupt<R: fmt::Display>(kind: ObjectKind, id: Id, reason: R) -> Self {
+		Self::Corrupt {
+			kind,
+			id,
+			reason: reason.to_string(),
+		}
+	}
+
+	/// Maps a failure to read the object `id` of kind `kind`.
+	pub fn unprocess(kind: ObjectKind, id: Id, err: PipelineError) -> Self {
+		if is_unsupported(&err) {
+			Self::Unsupported(err.to_string())
+		} else {
+			Self::corrupt(kind, id, err)
+		}
+	}
+
+	/// Maps a failure to decrypt the key `id`.
+	pub fn key(id: Id, err: KeyError) -> Self {
+		match err {
+			KeyError::WrongPassword => Self::WrongPassword,
+			KeyError::Encrypt(err @ EncryptError::Unsupported { .. }) => {
+				Self::Unsupported(err.to_string())
+			}
+			err => Self::corrupt(ObjectKind::Key, id, err),
+		}
+	}
+}
+
+const fn is_unsupported(err: &PipelineError) -> bool {
+	matches!(
+		err,
+		PipelineError::Compress(CompressError::Unsupported { .. })
+			| PipelineError::Encrypt(EncryptError::Unsupported { .. })
+			| PipelineError::Verify(VerifyError::Unsupported { .. })
+			| PipelineError::Format(FormatError::Unsupported { .. })
+	)
+}
+
+impl From<BackendError> for RepoError {
+	fn from(value: BackendError) -> Self {
+		Self::Backend(value)
+	}
+}
+
+impl From<PipelineError> for RepoError {
+	fn from(value: PipelineError) -> Self {
+		if is_unsupported(&value) {
+			Self::Unsupported(value.to_string())
+		} else {
+			Self::Process(value)
+		}
+	}
+}
+
+impl From<FormatError> for RepoError {
+	fn from(value: FormatError) -> Self {
+		PipelineError::from(value).into()
+	}
+}
+
+impl From<IdentifyError> for RepoError {
+	fn from(value: IdentifyError) -> Self {
+		Self::Unsupported(value.to_string())
+	}
+}
+
+impl fmt::Display for RepoError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::WrongPassword => f.write_str("Wrong password"),
+			Self::Locked { id, meta } => {
+				write!(f, "Repository is locked by {meta} with lock {id}")
+			}
+			Self::AppendOnly => {
+				f.write_str("Objects can not be removed from an append-only repository")
+			}
+			Self::Corrupt { kind, id, reason } => write!(f, "{kind} {id} is corrupt: {reason}"),
+			Self::Unsupported(inner) => f.write_str(inner),
+			Self::Process(inner) => write!(f, "Process: {inner}"),
+			Self::Backend(inner) => write!(f, "Backend: {inner}"),
+		}
+	}
+}
+
+impl std::error::Error for RepoError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Process(s) => Some(s),
+			Self::Backend(s) => Some(s),
+			_ => None,
+		}
+	}
+}
+
+pub type Result<T, E = RepoError> = std::result::Result<T, E>;
 
 #[derive(Debug)]
 pub struct Repo<B> {
@@ -26,28 +160,37 @@ pub struct Repo<B> {
 
 impl<B: BackendWrite> Repo<B> {
 	pub fn open(backend: B) -> Result<Self> {
-		backend.verify().unwrap();
+		backend.verify()?;
 
 		Ok(Self { backend })
 	}
 
 	fn get_key(&self, key_id: Id) -> Result<EncryptedKey> {
-		let key = self.backend.read_to_end(ObjectKind::Key, &key_id).unwrap();
+		let key = self.backend.read_to_end(ObjectKind::Key, &key_id)?;
 
-		Ok(Formatter::Cbor.parse(&key).unwrap())
+		Formatter::Cbor
+			.parse(&key)
+			.map_err(|err| RepoError::corrupt(ObjectKind::Key, key_id, err))
 	}
 
 	pub fn keys(&self) -> Result<B::Iter> {
-		self.backend.iter(ObjectKind::Key)
+		Ok(self.backend.iter(ObjectKind::Key)?)
 	}
 
 	pub fn find_key_id(&self, hex: &str) -> Result<Option<Find>> {
-		Ok(self.backend.find_id(ObjectKind::Key, hex).unwrap())
+		Ok(self.backend.find_id(ObjectKind::Key, hex)?)
 	}
 
-	pub fn try_unencrypted(self, key_id: Id) -> Result<DecryptedRepo<B>, (Self, Error)> {
-		let key = self.get_key(key_id).unwrap();
-		let key = key.try_unencrypted();
+	pub fn try_unencrypted(self, key_id: Id) -> Result<DecryptedRepo<B>, (Self, RepoError)> {
+		let key = match self.get_key(key_id) {
+			Ok(key) => key,
+			Err(err) => return Err((self, err)),
+		};
+
+		let key = match key.try_unencrypted() {
+			Ok(key) => key,
+			Err(err) => return Err((self, RepoError::key(key_id, err))),
+		};
 
 		Ok(DecryptedRepo {
 			backend: self.backend,
@@ -56,9 +199,20 @@ impl<B: BackendWrite> Repo<B> {
 		})
 	}
 
-	pub fn decrypt(self, key_id: Id, password: &[u8]) -> Result<DecryptedRepo<B>, (Self, Error)> {
-		let key = self.get_key(key_id).unwrap();
-		let key = key.decrypt(password);
+	pub fn decrypt(
+		self,
+		key_id: Id,
+		password: &[u8],
+	) -> Result<DecryptedRepo<B>, (Self, RepoError)> {
+		let key = match self.get_key(key_id) {
+			Ok(key) => key,
+			Err(err) => return Err((self, err)),
+		};
+
+		let key = match key.decrypt(password) {
+			Ok(key) => key,
+			Err(err) => return Err((self, RepoError::key(key_id, err))),
+		};
 
 		Ok(DecryptedRepo {
 			backend: self.backend,
@@ -89,57 +243,142 @@ impl<B: BackendWrite> DecryptedRepo<B> {
 	pub fn lock<CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
 		mut self,
 		marker: LockMarker<CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
-	) -> Result<LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>, (Self, Error)>
+	) -> Result<LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>, (Self, RepoError)>
 	where
 		LockMarker<CONFIG, INDEX, KEY, SNAPSHOT, PACK>: Into<LockState> + Copy,
 	{
-		// TODO: Check existing locks
-
 		let state: LockState = marker.into();
 		let meta: LockMeta = LockMeta::new();
 
+		if let Err(err) = lock::check_locks(&self.backend, &self.key, &state, &[]) {
+			return Err((self, err));
+		}
+
 		let lock = Lock { state, meta };
 		let lock = RepoLock {
 			lock,
 			_marker: marker,
 		};
 
-		// Fetch Config
-		let config: Config = {
-			let bytes = self
-				.backend
-				.read_to_end(ObjectKind::Config, &Id::ZERO)
-				.unwrap();
-			unprocess(Formatter::Cbor, &self.key, &bytes).unwrap()
+		let (config, lock_id) = match self.write_lock(&lock.lock) {
+			Ok(ok) => ok,
+			Err(err) => return Err((self, err)),
 		};
 
-		// Write lock
-		let lock_id = {
-			let identifier = config.process.identifier.create();
-			let pipeline = ChunkPipeline::new(config.process, self.key.clone());
+		// Another client might have written a conflicting lock in the meantime
+		if let Err(err) = lock::check_locks(&self.backend, &self.key, &state, &[lock_id]) {
+			if let Err(err) = self.backend.remove(ObjectKind::Lock, &lock_id) {
+				log::error!("Failed to remove lock {lock_id:x}: {err}");
+			}
+
+			return Err((self, err));
+		}
+
+		let heartbeat = Heartbeat::start(
+			self.backend.clone(),
+			self.key.clone(),
+			config,
+			lock.lock.clone(),
+			lock_id,
+			LockMeta::REFRESH_INTERVAL,
+		);
+
+		Ok(LockedRepo {
+			backend: self.backend,
+			key: self.key,
+			key_id: self.key_id,
+			lock,
+			heartbeat: Some(heartbeat),
+			config,
+		})
+	}
 
-			let bytes = Formatter::Cbor.format(&lock.lock).unwrap();
-			let id = identifier.identify(&self.key, &bytes).unwrap();
+	/// Opens the repository for reading without writing a lock.
+	///
+	/// All access is shared, so the returned repository only implements the
+	/// `*Read` traits. See [`Self::append_only`] for the caveats.
+	pub fn read_only(self) -> Result<ReadOnlyRepo<B>, (Self, RepoError)> {
+		self.append_only(LockMarker::READ)
+	}
 
-			let bytes = pipeline.process(&bytes).unwrap();
+	/// Opens the repository with the access of `marker` without writing a lock
+	/// and without ever removing objects.
+	///
+	/// This is meant for storage which rejects deletes (e.g. WORM storage).
+	/// Existing locks are still checked for conflicts, but as no lock is
+	/// written other clients can not see this one. Removing objects fails with
+	/// [`RepoError::AppendOnly`].
+	pub fn append_only<CONFIG, INDEX, KEY, SNAPSHOT, PACK>(
+		self,
+		marker: LockMarker<CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
+	) -> Result<LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>, (Self, RepoError)>
+	where
+		LockMarker<CONFIG, INDEX, KEY, SNAPSHOT, PACK>: Into<LockState> + Copy,
+	{
+		let state: LockState = marker.into();
+		let meta: LockMeta = LockMeta::new();
 
-			self.backend
-				.write_all(ObjectKind::Lock, &id, &bytes)
-				.unwrap();
+		if let Err(err) = lock::check_locks(&self.backend, &self.key, &state, &[]) {
+			return Err((self, err));
+		}
 
-			id
+		let config = match self.config() {
+			Ok(config) => config,
+			Err(err) => return Err((self, err)),
 		};
 
 		Ok(LockedRepo {
 			backend: self.backend,
 			key: self.key,
 			key_id: self.key_id,
-			lock,
-			lock_id,
+			lock: RepoLock {
+				lock: Lock { state, meta },
+				_marker: marker,
+			},
+			heartbeat: None,
 			config,
 		})
 	}
 
+	/// Caches cacheable objects in a directory for this repository within
+	/// `root` (see [`Cache`]).
+	///
+	/// Caching is disabled if `root` is `None`.
+	pub fn cached<P: AsRef<Path>>(self, root: Option<P>) -> Result<DecryptedRepo<Cache<B>>> {
+		let cache = match root {
+			Some(root) => {
+				let config = self.config()?;
+
+				let path = root.as_ref().join(config.id.to_hex());
+				log::debug!("Using cache {}", path.display());
+
+				Cache::new(self.backend, path)
+			}
+			None => Cache::disabled(self.backend),
+		};
+
+		Ok(DecryptedRepo {
+			backend: cache,
+			key: self.key,
+			key_id: self.key_id,
+		})
+	}
+
+	pub fn config(&self) -> Result<Config> {
+		let bytes = self.backend.read_to_end(ObjectKind::Config, &Id::ZERO)?;
+
+		unprocess(Formatter::Cbor, &self.key, &bytes)
+			.map_err(|err| RepoError::unprocess(ObjectKind::Config, Id::ZERO, err))
+	}
+
+	/// Fetches the config and writes `lock`.
+	fn write_lock(&mut self, lock: &Lock) -> Result<(Config, Id)> {
+		let config = self.config()?;
+		let lock_id = lock::write_lock(&mut self.backend, &self.key, &config, lock)?;
+
+		Ok((config, lock_id))
+	}
+
 	pub fn key(&self) -> &Key {
 		&self.key
 	}
@@ -243,21 +482,161 @@ pub struct LockedRepo<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK> {
 	key: Key,
 	key_id: Id,
 	lock: RepoLock<CONFIG, INDEX, KEY, SNAPSHOT, PACK>,
-	lock_id: Id,
+	/// `None` if no lock was written (see [`DecryptedRepo::append_only`])
+	heartbeat: Option<Heartbeat>,
 	config: Config,
 }
 
+/// A repository which can only be read and has no lock written.
+///
+/// See [`DecryptedRepo::read_only`].
+pub type ReadOnlyRepo<B> = LockedRepo<B, Shared, Shared, Shared, Shared, Shared>;
+
 impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
 	LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
 {
-	fn cleanup(&mut self) -> Result<()> {
-		log::debug!("Cleaning up lock {:x}", self.lock_id);
+	fn cleanup(&mut self) -> backend::Result<()> {
+		// Also makes a repeated cleanup a no-op
+		let Some(mut heartbeat) = self.heartbeat.take() else {
+			return Ok(());
+		};
 
-		self.backend
-			.remove(ObjectKind::Lock, &self.lock_id)
-			.unwrap();
+		let lock_id = heartbeat.stop();
+		log::debug!("Cleaning up lock {lock_id:x}");
 
-		Ok(())
+		self.backend.remove(ObjectKind::Lock, &lock_id)
+	}
+
+	/// Removes an object unless the repository was opened append-only.
+	pub(crate) fn remove_object(&mut self, kind: ObjectKind, id: &Id) -> Result<()> {
+		if self.heartbeat.is_none() {
+			return Err(RepoError::AppendOnly);
+		}
+
+		Ok(self.backend.remove(kind, id)?)
+	}
+
+	/// Changes the access of the held lock to the one of `marker`, e.g. to
+	/// upgrade shared access to some object kinds to exclusive access or to
+	/// downgrade it again.
+	///
+	/// The new lock is written before the old one is removed, so other clients
+	/// never see the repository unlocked in between. If the new lock grants
+	/// more access and conflicts with the lock of another client, it is
+	/// removed again and `self` keeps its current lock.
+	pub fn relock<C, I, K, S, P>(
+		mut self,
+		marker: LockMarker<C, I, K, S, P>,
+	) -> Result<LockedRepo<B, C, I, K, S, P>, (Self, RepoError)>
+	where
+		LockMarker<C, I, K, S, P>: Into<LockState> + Copy,
+	{
+		let state: LockState = marker.into();
+		let upgrade = !self.lock.lock.state.contains(&state);
+
+		let mut lock = self.lock.lock.clone();
+		lock.state = state;
+		lock.meta.refreshed = Some(Utc::now());
+
+		let new_id = match self.replace_lock(&lock, upgrade) {
+			Ok(id) => id,
+			Err(err) => return Err((self, err)),
+		};
+
+		let (backend, key, key_id, config) = self.into_parts();
+
+		let heartbeat = new_id.map(|id| {
+			Heartbeat::start(
+				backend.clone(),
+				key.clone(),
+				config,
+				lock.clone(),
+				id,
+				LockMeta::REFRESH_INTERVAL,
+			)
+		});
+
+		Ok(LockedRepo {
+			backend,
+			key,
+			key_id,
+			lock: RepoLock {
+				lock,
+				_marker: marker,
+			},
+			heartbeat,
+			config,
+		})
+	}
+
+	/// Writes `lock` in place of the current one and returns its id.
+	///
+	/// Nothing is written if the repository was opened append-only.
+	fn replace_lock(&mut self, lock: &Lock, upgrade: bool) -> Result<Option<Id>> {
+		let Some(heartbeat) = &mut self.heartbeat else {
+			if upgrade {
+				lock::check_locks(&self.backend, &self.key, &lock.state, &[])?;
+			}
+
+			return Ok(None);
+		};
+
+		// Keep the heartbeat from replacing the old lock while switching
+		let old_id = heartbeat.stop();
+
+		let new_id = match lock::write_lock(&mut self.backend, &self.key, &self.config, lock) {
+			Ok(id) => id,
+			Err(err) => {
+				self.restart_heartbeat(old_id);
+				return Err(err);
+			}
+		};
+
+		if upgrade {
+			if let Err(err) =
+				lock::check_locks(&self.backend, &self.key, &lock.state, &[old_id, new_id])
+			{
+				if let Err(err) = self.backend.remove(ObjectKind::Lock, &new_id) {
+					log::error!("Failed to remove lock {new_id:x}: {err}");
+				}
+
+				self.restart_heartbeat(old_id);
+				return Err(err);
+			}
+		}
+
+		// Not fatal as the old lock becomes stale without a heartbeat
+		if let Err(err) = self.backend.remove(ObjectKind::Lock, &old_id) {
+			log::error!("Failed to remove lock {old_id:x}: {err}");
+		}
+
+		log::debug!("Replaced lock {old_id:x} with {new_id:x}");
+
+		Ok(Some(new_id))
+	}
+
+	fn restart_heartbeat(&mut self, lock_id: Id) {
+		self.heartbeat = Some(Heartbeat::start(
+			self.backend.clone(),
+			self.key.clone(),
+			self.config,
+			self.lock.lock.clone(),
+			lock_id,
+			LockMeta::REFRESH_INTERVAL,
+		));
+	}
+
+	/// Takes the repository apart without removing the lock.
+	fn into_parts(mut self) -> (B, Key, Id, Config) {
+		// Without a heartbeat dropping `self` leaves the lock in place
+		drop(self.heartbeat.take());
+
+		(
+			self.backend.clone(),
+			self.key.clone(),
+			self.key_id,
+			self.config,
+		)
 	}
 
 	pub fn key(&self) -> &Key {
@@ -272,19 +651,204 @@ impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
 		&self.lock.lock
 	}
 
-	pub fn lock_id(&self) -> &Id {
-		&self.lock_id
+	/// Returns the id of the lock, which changes with every refresh.
+	///
+	/// Returns `None` if no lock was written.
+	pub fn lock_id(&self) -> Option<Id> {
+		self.heartbeat.as_ref().map(Heartbeat::lock_id)
+	}
+
+	/// Returns `true` if the repository was opened append-only, in which
+	/// case no objects can be removed.
+	pub fn is_append_only(&self) -> bool {
+		self.heartbeat.is_none()
 	}
 
 	pub fn config(&self) -> &Config {
 		&self.config
 	}
+
+	/// Verifies the structure of the backend.
+	pub fn verify(&self) -> Result<()> {
+		Ok(self.backend.verify()?)
+	}
+
+	pub(crate) fn pipeline(&self) -> ChunkPipeline {
+		ChunkPipeline::new(self.config.process, self.key.clone())
+	}
+
+	/// Writes `value` as a new object of kind [`RepoObject::KIND`].
+	///
+	/// The id is derived from the formatted but unprocessed bytes.
+	pub(crate) fn write_object<V: RepoObject>(&mut self, value: &V) -> Result<Id> {
+		let identifier = self.config.process.identifier.create();
+
+		let bytes = Formatter::Cbor.format(value)?;
+		let id = identifier.identify(&self.key, &bytes)?;
+
+		let bytes = self.pipeline().process(&bytes)?;
+
+		self.backend.write_all(V::KIND, &id, &bytes)?;
+
+		Ok(id)
+	}
+}
+
+impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+	LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+where
+	INDEX: AccessExclusive,
+	SNAPSHOT: AccessExclusive,
+	PACK: AccessExclusive,
+{
+	/// Removes leftovers of interrupted writes which are at least `age` old.
+	pub fn clean(&mut self, age: Duration) -> Result<u64> {
+		if self.heartbeat.is_none() {
+			return Err(RepoError::AppendOnly);
+		}
+
+		Ok(self.backend.clean(age)?)
+	}
 }
 
 impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK> Drop
 	for LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
 {
 	fn drop(&mut self) {
-		self.cleanup().unwrap();
+		// Panicking here would abort an unwinding process
+		if let Err(err) = self.cleanup() {
+			log::error!("Failed to remove the lock: {err}");
+		}
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use super::*;
+	use crate::backend::memory::Memory;
+	use crate::obj::lock::{Exclusive, LockAccess};
+	use crate::obj::snapshot::Snapshot;
+	use crate::process::chunk::ChunkerParams;
+	use crate::process::compress::CompressionParams;
+	use crate::process::encrypt::EncryptionParams;
+	use crate::process::identify::IdentifierParams;
+	use crate::process::prune::{PruneError, Pruner};
+	use crate::process::verify::VerifierParams;
+	use crate::process::ProcessOptions;
+	use crate::repo::snapshot::{SnapshotRead, SnapshotUpdate};
+
+	/// Creates an empty repository in memory.
+	pub fn repo() -> DecryptedRepo<Memory> {
+		let mut backend = Memory::new();
+		backend.create().unwrap();
+
+		let key = Key::random();
+		let config = Config::new(ProcessOptions {
+			chunker: ChunkerParams::FastCdc(Default::default()),
+			identifier: IdentifierParams::Blake3,
+			compression: CompressionParams::None,
+			encryption: EncryptionParams::None,
+			verifier: VerifierParams::None,
+		});
+
+		let bytes = Formatter::Cbor.format(&config).unwrap();
+		let bytes = ChunkPipeline::new(config.process, key.clone())
+			.process(&bytes)
+			.unwrap();
+		backend
+			.write_all(ObjectKind::Config, &Id::ZERO, &bytes)
+			.unwrap();
+
+		DecryptedRepo {
+			backend,
+			key,
+			key_id: Id::ZERO,
+		}
+	}
+
+	#[test]
+	fn relock() {
+		let repo = repo();
+		let backend = repo.backend.clone();
+		let other = DecryptedRepo {
+			backend: backend.clone(),
+			key: repo.key.clone(),
+			key_id: repo.key_id,
+		};
+
+		let locked = repo.lock(LockMarker::READ).unwrap();
+		let other = other.lock(LockMarker::READ).unwrap();
+
+		let (locked, err) = locked
+			.relock(LockMarker::READ.index::<Exclusive>())
+			.unwrap_err();
+		assert!(matches!(err, RepoError::Locked { id, .. } if Some(id) == other.lock_id()));
+		assert_eq!(backend.len(ObjectKind::Lock), 2);
+
+		drop(other);
+
+		let locked = locked
+			.relock(LockMarker::READ.index::<Exclusive>())
+			.unwrap();
+		assert_eq!(locked.lock().state.index, LockAccess::Exclusive);
+		assert_eq!(backend.len(ObjectKind::Lock), 1);
+
+		let locked = locked.relock(LockMarker::READ.index::<Shared>()).unwrap();
+		let other = DecryptedRepo {
+			backend: backend.clone(),
+			key: locked.key.clone(),
+			key_id: locked.key_id,
+		};
+		let other = other.lock(LockMarker::READ).unwrap();
+		assert_eq!(backend.len(ObjectKind::Lock), 2);
+
+		drop((locked, other));
+		assert_eq!(backend.len(ObjectKind::Lock), 0);
+	}
+
+	#[test]
+	fn append_only() {
+		let repo = repo();
+		let backend = repo.backend.clone();
+		let key = repo.key.clone();
+
+		let mut append = repo.append_only(LockMarker::WRITE).unwrap();
+		assert_eq!(append.lock_id(), None);
+
+		let id = append.snapshot_write(&Snapshot::default()).unwrap();
+		assert!(matches!(
+			append.snapshot_remove(&id),
+			Err(RepoError::AppendOnly)
+		));
+		assert!(matches!(
+			append.clean(Duration::ZERO),
+			Err(RepoError::AppendOnly)
+		));
+		assert!(matches!(
+			Pruner::new().prune(&mut append),
+			Err(PruneError::Repo(RepoError::AppendOnly))
+		));
+
+		let locked = DecryptedRepo {
+			backend: backend.clone(),
+			key: key.clone(),
+			key_id: Id::ZERO,
+		};
+		let locked = locked.lock(LockMarker::READ).unwrap();
+		drop(append);
+		assert_eq!(backend.len(ObjectKind::Lock), 1);
+		assert_eq!(backend.len(ObjectKind::Snapshot), 1);
+
+		let reader = DecryptedRepo {
+			backend: backend.clone(),
+			key,
+			key_id: Id::ZERO,
+		};
+		let reader = reader.read_only().unwrap();
+		assert_eq!(reader.snapshots_read().unwrap().len(), 1);
+		assert_eq!(backend.len(ObjectKind::Lock), 1);
+
+		drop((reader, locked));
+		assert_eq!(backend.len(ObjectKind::Lock), 0);
 	}
 }
@@ -0,0 +1,151 @@
+use crate::backend::ext::{Find, FindIdExt, ReadToEnd};
+use crate::backend::{self, BackendWrite};
+use crate::id::Id;
+use crate::obj::index::BlobEntry;
+use crate::obj::key::Key;
+use crate::obj::lock::sealed::{AccessExclusive, AccessShared};
+use crate::obj::pack::{Pack, PackHeader};
+use crate::obj::{ObjectKind, ObjectMetadata};
+use crate::process::format::Formatter;
+use crate::process::pipeline::{unprocess, unprocess_bytes};
+use crate::repo::{LockedRepo, RepoError, Result};
+
+const OBJ: ObjectKind = ObjectKind::Pack;
+
+pub trait PackRead {
+	type Iter: Iterator<Item = backend::Result<Id>>;
+
+	fn pack_exists(&self, id: &Id) -> Result<()>;
+	fn packs(&self) -> Result<Self::Iter>;
+	fn pack_meta(&self, id: &Id) -> Result<ObjectMetadata>;
+	fn pack_read(&self, id: &Id) -> Result<Vec<u8>>;
+	fn pack_read_at(&self, id: &Id, offset: u32, buf: &mut [u8]) -> Result<usize>;
+	fn packs_find(&self, ids: &[&str]) -> Result<Vec<Find>>;
+	fn pack_find(&self, id: &str) -> Result<Option<Find>>;
+}
+
+impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK> PackRead
+	for LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+where
+	PACK: AccessShared,
+{
+	type Iter = B::Iter;
+
+	fn pack_exists(&self, id: &Id) -> Result<()> {
+		Ok(self.backend.exists(OBJ, id)?)
+	}
+
+	fn packs(&self) -> Result<B::Iter> {
+		Ok(self.backend.iter(OBJ)?)
+	}
+
+	fn pack_meta(&self, id: &Id) -> Result<ObjectMetadata> {
+		Ok(self.backend.meta(OBJ, id)?)
+	}
+
+	fn pack_read(&self, id: &Id) -> Result<Vec<u8>> {
+		Ok(self.backend.read_to_end(OBJ, id)?)
+	}
+
+	fn pack_read_at(&self, id: &Id, offset: u32, buf: &mut [u8]) -> Result<usize> {
+		Ok(self.backend.read_at(OBJ, id, offset, buf)?)
+	}
+
+	fn packs_find(&self, ids: &[&str]) -> Result<Vec<Find>> {
+		Ok(self.backend.find_ids(OBJ, ids)?)
+	}
+
+	fn pack_find(&self, id: &str) -> Result<Option<Find>> {
+		Ok(self.backend.find_id(OBJ, id)?)
+	}
+}
+
+pub trait PackUpdate {
+	fn pack_write(&mut self, id: &Id, bytes: &[u8]) -> Result<()>;
+	fn pack_remove(&mut self, id: &Id) -> Result<()>;
+}
+
+impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK> PackUpdate
+	for LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+where
+	PACK: AccessExclusive,
+{
+	fn pack_write(&mut self, id: &Id, bytes: &[u8]) -> Result<()> {
+		Ok(self.backend.write_all(OBJ, id, bytes)?)
+	}
+
+	fn pack_remove(&mut self, id: &Id) -> Result<()> {
+		self.remove_object(OBJ, id)
+	}
+}
+
+/// Reads single blobs or the header of packs without fetching the whole pack.
+#[derive(Debug, Clone, Copy)]
+pub struct PackReader<'a, R> {
+	repo: &'a R,
+	key: &'a Key,
+}
+
+impl<'a, R: PackRead> PackReader<'a, R> {
+	pub const fn new(repo: &'a R, key: &'a Key) -> Self {
+		Self { repo, key }
+	}
+
+	/// Reads and unprocesses the header of the pack `pack`.
+	pub fn read_header(&self, pack: &Id) -> Result<PackHeader> {
+		let corrupt = |reason| RepoError::corrupt(OBJ, *pack, reason);
+
+		let len: u32 = self
+			.repo
+			.pack_meta(pack)?
+			.len
+			.try_into()
+			.map_err(|_| corrupt("Too large"))?;
+
+		let header_len = {
+			let offset = len
+				.checked_sub(Pack::HEADER_LEN_SIZE)
+				.ok_or_else(|| corrupt("Too short"))?;
+			let mut buf = [0u8; Pack::HEADER_LEN_SIZE as usize];
+			self.repo.pack_read_at(pack, offset, &mut buf)?;
+
+			u32::from_le_bytes(buf)
+		};
+
+		let offset = len
+			.checked_sub(Pack::HEADER_LEN_SIZE)
+			.and_then(|len| len.checked_sub(header_len))
+			.ok_or_else(|| corrupt("Invalid header length"))?;
+
+		let header = self.read_raw(pack, offset, header_len)?;
+
+		unprocess(Formatter::Cbor, self.key, &header)
+			.map_err(|err| RepoError::unprocess(OBJ, *pack, err))
+	}
+
+	/// Reads and unprocesses the blob `entry` from the pack `pack`.
+	pub fn read_blob(&self, pack: &Id, entry: &BlobEntry) -> Result<Vec<u8>> {
+		let bytes = self.read_raw(pack, entry.offset, entry.processed_len)?;
+
+		unprocess_bytes(&Formatter::Cbor, self.key, &bytes)
+			.map_err(|err| RepoError::unprocess(OBJ, *pack, err))
+	}
+
+	/// Reads `len` processed bytes at `offset` from the pack `pack`.
+	pub fn read_raw(&self, pack: &Id, offset: u32, len: u32) -> Result<Vec<u8>> {
+		let mut buf = vec![0u8; len as usize];
+		self.repo.pack_read_at(pack, offset, &mut buf)?;
+
+		Ok(buf)
+	}
+}
+
+impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+	LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+where
+	PACK: AccessShared,
+{
+	pub const fn pack_reader(&self) -> PackReader<'_, Self> {
+		PackReader::new(self, &self.key)
+	}
+}
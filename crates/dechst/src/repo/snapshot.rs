@@ -0,0 +1,85 @@
+use crate::backend::ext::{Find, FindIdExt, ReadToEnd};
+use crate::backend::{self, BackendWrite};
+use crate::id::{Id, Idd};
+use crate::obj::lock::sealed::{AccessExclusive, AccessShared};
+use crate::obj::snapshot::Snapshot;
+use crate::obj::ObjectKind;
+use crate::process::format::Formatter;
+use crate::process::pipeline::unprocess;
+use crate::repo::{LockedRepo, RepoError, Result};
+
+const OBJ: ObjectKind = ObjectKind::Snapshot;
+
+pub trait SnapshotRead {
+	type Iter: Iterator<Item = backend::Result<Id>>;
+
+	fn snapshot_exists(&self, id: &Id) -> Result<()>;
+	fn snapshots(&self) -> Result<Self::Iter>;
+	fn snapshot_read(&self, id: &Id) -> Result<Snapshot>;
+	fn snapshots_read(&self) -> Result<Vec<Idd<Snapshot>>>;
+	fn snapshots_find(&self, ids: &[&str]) -> Result<Vec<Find>>;
+	fn snapshot_find(&self, id: &str) -> Result<Option<Find>>;
+}
+
+impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK> SnapshotRead
+	for LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+where
+	SNAPSHOT: AccessShared,
+{
+	type Iter = B::Iter;
+
+	fn snapshot_exists(&self, id: &Id) -> Result<()> {
+		Ok(self.backend.exists(OBJ, id)?)
+	}
+
+	fn snapshots(&self) -> Result<B::Iter> {
+		Ok(self.backend.iter(OBJ)?)
+	}
+
+	fn snapshot_read(&self, id: &Id) -> Result<Snapshot> {
+		let bytes = self.backend.read_to_end(OBJ, id)?;
+
+		let snapshot = unprocess(Formatter::Cbor, &self.key, &bytes)
+			.map_err(|err| RepoError::unprocess(OBJ, *id, err))?;
+
+		Ok(snapshot)
+	}
+
+	fn snapshots_read(&self) -> Result<Vec<Idd<Snapshot>>> {
+		let mut snapshots = Vec::new();
+
+		for id in self.snapshots()? {
+			let id = id?;
+			snapshots.push(id.idd(self.snapshot_read(&id)?));
+		}
+
+		Ok(snapshots)
+	}
+
+	fn snapshots_find(&self, ids: &[&str]) -> Result<Vec<Find>> {
+		Ok(self.backend.find_ids(OBJ, ids)?)
+	}
+
+	fn snapshot_find(&self, id: &str) -> Result<Option<Find>> {
+		Ok(self.backend.find_id(OBJ, id)?)
+	}
+}
+
+pub trait SnapshotUpdate {
+	fn snapshot_write(&mut self, snapshot: &Snapshot) -> Result<Id>;
+	fn snapshot_remove(&mut self, id: &Id) -> Result<()>;
+}
+
+impl<B: BackendWrite, CONFIG, INDEX, KEY, SNAPSHOT, PACK> SnapshotUpdate
+	for LockedRepo<B, CONFIG, INDEX, KEY, SNAPSHOT, PACK>
+where
+	SNAPSHOT: AccessExclusive,
+{
+	fn snapshot_write(&mut self, snapshot: &Snapshot) -> Result<Id> {
+		self.write_object(snapshot)
+	}
+
+	fn snapshot_remove(&mut self, id: &Id) -> Result<()> {
+		self.remove_object(OBJ, id)
+	}
+}
@@ -0,0 +1,294 @@
+use std::fs::{self, File, ReadDir};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use super::Target;
+use crate::obj::tree::node::{Node, NodeKind};
+use crate::path::Segment;
+
+#[derive(Debug)]
+pub struct FsTarget(PathBuf);
+
+impl FsTarget {
+	pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+		Self(path.into())
+	}
+
+	fn resolve_item(&self, item: &PathBuf) -> PathBuf {
+		self.0.join(item)
+	}
+}
+
+impl Target for FsTarget {
+	type Error = std::io::Error;
+	type Item = PathBuf;
+	type Iter = Iter;
+	type Write = BufWriter<File>;
+
+	fn item(&self, parent: Option<&Self::Item>, name: &Segment) -> Self::Item {
+		let name = name.to_os_str();
+
+		if let Some(parent) = parent {
+			parent.join(name)
+		} else {
+			PathBuf::from(name.into_owned())
+		}
+	}
+
+	fn iter(&self, item: Option<&Self::Item>) -> Result<Self::Iter, Self::Error> {
+		let path = if let Some(item) = item {
+			self.resolve_item(item)
+		} else {
+			self.0.to_path_buf()
+		};
+
+		Ok(Iter {
+			parent: item.cloned().unwrap_or_default(),
+			inner: path.read_dir()?,
+		})
+	}
+
+	fn node(&self, item: &Self::Item) -> Result<Option<Node>, Self::Error> {
+		let path = self.resolve_item(item);
+
+		let fsmeta = match fs::symlink_metadata(&path) {
+			Ok(meta) => meta,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(err),
+		};
+
+		let kind = (path.as_ref(), &fsmeta).try_into()?;
+		let name = Segment::from(path.file_name().unwrap().to_os_string());
+
+		#[cfg(target_family = "unix")]
+		let meta = crate::os::unix::Metadata::from(&fsmeta).into();
+		#[cfg(target_family = "windows")]
+		let meta = crate::os::windows::Metadata::from(&fsmeta).into();
+
+		Ok(Some(Node { name, kind, meta }))
+	}
+
+	fn create_root(&self) -> Result<(), Self::Error> {
+		fs::create_dir_all(&self.0)
+	}
+
+	fn create(&self, item: &Self::Item, node: &Node) -> Result<(), Self::Error> {
+		let path = self.resolve_item(item);
+
+		match &node.kind {
+			NodeKind::Directory { .. } => match fs::create_dir(&path) {
+				// An existing directory is reused
+				Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && path.is_dir() => {
+					Ok(())
+				}
+				res => res,
+			},
+			NodeKind::File { .. } => File::create(path).map(|_| ()),
+			kind => create_special(&path, kind),
+		}
+	}
+
+	fn write(&self, item: &Self::Item) -> Result<Self::Write, Self::Error> {
+		let file = File::create(self.resolve_item(item))?;
+		Ok(BufWriter::new(file))
+	}
+
+	fn remove(&self, item: &Self::Item) -> Result<(), Self::Error> {
+		let path = self.resolve_item(item);
+
+		if fs::symlink_metadata(&path)?.is_dir() {
+			fs::remove_dir_all(path)
+		} else {
+			fs::remove_file(path)
+		}
+	}
+
+	fn finish(&self, item: &Self::Item, node: &Node) -> Result<(), Self::Error> {
+		apply_meta(&self.resolve_item(item), node)
+	}
+}
+
+#[derive(Debug)]
+pub struct Iter {
+	parent: PathBuf,
+	inner: ReadDir,
+}
+
+impl Iterator for Iter {
+	type Item = Result<PathBuf, std::io::Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.inner
+			.next()
+			.map(|o| o.map(|d| self.parent.join(d.file_name())))
+	}
+}
+
+#[cfg(target_family = "unix")]
+// `dev_t` differs between platforms
+#[allow(clippy::useless_conversion)]
+fn create_special(path: &Path, kind: &NodeKind) -> Result<(), std::io::Error> {
+	use std::os::unix::ffi::OsStrExt;
+
+	let cpath = |path: &Path| {
+		std::ffi::CString::new(path.as_os_str().as_bytes())
+			.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))
+	};
+
+	let dev_t = |device: u64| {
+		libc::dev_t::try_from(device).map_err(|_| {
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				format!("Device number {device} is not supported on this platform"),
+			)
+		})
+	};
+
+	let ret = match kind {
+		NodeKind::Symlink { target, .. } => {
+			return std::os::unix::fs::symlink(target.to_os_str(), path);
+		}
+		NodeKind::Fifo => unsafe { libc::mkfifo(cpath(path)?.as_ptr(), 0o600) },
+		NodeKind::Device { device } => unsafe {
+			libc::mknod(
+				cpath(path)?.as_ptr(),
+				libc::S_IFBLK | 0o600,
+				dev_t(*device)?,
+			)
+		},
+		NodeKind::CharacterDevice { device } => unsafe {
+			libc::mknod(
+				cpath(path)?.as_ptr(),
+				libc::S_IFCHR | 0o600,
+				dev_t(*device)?,
+			)
+		},
+		NodeKind::Socket => {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::Unsupported,
+				"Sockets can not be restored",
+			));
+		}
+		NodeKind::File { .. } | NodeKind::Directory { .. } => {
+			unreachable!("Handled by `FsTarget::create`")
+		}
+	};
+
+	if ret == 0 {
+		Ok(())
+	} else {
+		Err(std::io::Error::last_os_error())
+	}
+}
+
+#[cfg(target_family = "unix")]
+fn apply_meta(path: &Path, node: &Node) -> Result<(), std::io::Error> {
+	use std::os::unix::ffi::OsStrExt;
+	use std::os::unix::fs::PermissionsExt;
+
+	use chrono::{DateTime, Utc};
+
+	use crate::os::Metadata;
+
+	let Metadata::Unix(meta) = &node.meta else {
+		log::debug!("Skipping non unix metadata for {}", path.display());
+		return Ok(());
+	};
+
+	let cpath = std::ffi::CString::new(path.as_os_str().as_bytes())
+		.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+
+	// Ownership (must be set before the permissions as it may clear the
+	// setuid/setgid bits)
+	if meta.user.uid.is_some() || meta.user.gid.is_some() {
+		// `-1` (as unsigned) leaves the respective id unchanged
+		let uid = libc::uid_t::from(meta.user.uid.unwrap_or(u32::MAX));
+		let gid = libc::gid_t::from(meta.user.gid.unwrap_or(u32::MAX));
+
+		if unsafe { libc::lchown(cpath.as_ptr(), uid, gid) } != 0 {
+			// Usually only permitted for privileged users
+			log::debug!(
+				"Failed to set owner of {}: {}",
+				path.display(),
+				std::io::Error::last_os_error()
+			);
+		}
+	}
+
+	// Permissions (symlinks do not have their own permissions)
+	if !matches!(node.kind, NodeKind::Symlink { .. }) {
+		fs::set_permissions(path, fs::Permissions::from_mode(meta.perm.mode & 0o7777))?;
+	}
+
+	// Times
+	// `time_t` and `c_long` differ between platforms
+	#[allow(clippy::useless_conversion)]
+	fn to_timespec(time: Option<DateTime<Utc>>) -> Result<libc::timespec, std::io::Error> {
+		let Some(time) = time else {
+			return Ok(libc::timespec {
+				tv_sec: 0,
+				tv_nsec: libc::UTIME_OMIT,
+			});
+		};
+
+		let unsupported = || {
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				format!("Time {time} is not supported on this platform"),
+			)
+		};
+
+		Ok(libc::timespec {
+			tv_sec: time.timestamp().try_into().map_err(|_| unsupported())?,
+			tv_nsec: i64::from(time.timestamp_subsec_nanos())
+				.try_into()
+				.map_err(|_| unsupported())?,
+		})
+	}
+
+	let times = [
+		to_timespec(meta.time.access)?,
+		to_timespec(meta.time.modify)?,
+	];
+
+	let ret = unsafe {
+		libc::utimensat(
+			libc::AT_FDCWD,
+			cpath.as_ptr(),
+			times.as_ptr(),
+			libc::AT_SYMLINK_NOFOLLOW,
+		)
+	};
+
+	if ret == 0 {
+		Ok(())
+	} else {
+		Err(std::io::Error::last_os_error())
+	}
+}
+
+#[cfg(target_family = "windows")]
+fn create_special(path: &Path, kind: &NodeKind) -> Result<(), std::io::Error> {
+	use crate::obj::tree::node::TargetHint;
+
+	match kind {
+		NodeKind::Symlink {
+			target,
+			hint: Some(TargetHint::Directory),
+		} => std::os::windows::fs::symlink_dir(target.to_os_str(), path),
+		NodeKind::Symlink { target, .. } => {
+			std::os::windows::fs::symlink_file(target.to_os_str(), path)
+		}
+		_ => Err(std::io::Error::new(
+			std::io::ErrorKind::Unsupported,
+			"Node kind can not be restored on windows",
+		)),
+	}
+}
+
+#[cfg(target_family = "windows")]
+fn apply_meta(path: &Path, _: &Node) -> Result<(), std::io::Error> {
+	// TODO: Restore attributes and times
+	log::debug!("Skipping metadata for {}", path.display());
+	Ok(())
+}
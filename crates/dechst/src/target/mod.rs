@@ -0,0 +1,71 @@
+use std::io::Write;
+
+use crate::obj::tree::node::Node;
+use crate::path::Segment;
+
+pub mod fs;
+
+/// Counterpart to [`Source`](crate::source::Source) which materializes
+/// [`Node`]s restored from a snapshot.
+///
+/// Items are addressed relative to the root of the target. `None` always
+/// refers to the root itself.
+pub trait Target {
+	type Error: std::error::Error;
+	type Item: Clone;
+	type Write: Write;
+
+	type Iter: Iterator<Item = Result<Self::Item, Self::Error>>;
+
+	/// Returns the item with the name `name` within `parent`.
+	fn item(&self, parent: Option<&Self::Item>, name: &Segment) -> Self::Item;
+
+	/// Lists all existing items within `item`.
+	fn iter(&self, item: Option<&Self::Item>) -> Result<Self::Iter, Self::Error>;
+
+	/// Returns the existing node of `item` or `None` if it does not exist.
+	fn node(&self, item: &Self::Item) -> Result<Option<Node>, Self::Error>;
+
+	/// Creates the (empty) root of the target if it does not yet exist.
+	fn create_root(&self) -> Result<(), Self::Error>;
+
+	/// Creates any node which is not a file (e.g. directory, symlink, ...).
+	fn create(&self, item: &Self::Item, node: &Node) -> Result<(), Self::Error>;
+
+	/// Creates or truncates the file `item` and returns a writer for its
+	/// contents.
+	fn write(&self, item: &Self::Item) -> Result<Self::Write, Self::Error>;
+
+	/// Removes `item` and, if it is a directory, all its children.
+	fn remove(&self, item: &Self::Item) -> Result<(), Self::Error>;
+
+	/// Applies the metadata (permissions, ownership, times, ...) of `node`.
+	///
+	/// This is called after all children of a directory were restored.
+	fn finish(&self, item: &Self::Item, node: &Node) -> Result<(), Self::Error>;
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreMode {
+	/// Only create new files but dont touch existing ones
+	#[default]
+	OnlyNew,
+	/// Only update existing files but dont create new ones
+	OnlyExisting,
+	/// Restore all files and delete any files not included in the backup
+	Clean,
+}
+
+impl RestoreMode {
+	pub const fn creates(&self) -> bool {
+		matches!(self, Self::OnlyNew | Self::Clean)
+	}
+
+	pub const fn updates(&self) -> bool {
+		matches!(self, Self::OnlyExisting | Self::Clean)
+	}
+
+	pub const fn removes(&self) -> bool {
+		matches!(self, Self::Clean)
+	}
+}